@@ -0,0 +1,72 @@
+use std::error;
+use std::fmt;
+
+use hyper;
+use hyper::StatusCode;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidUrl(String), // the server URL, or a request URL built from it and an id, could not be parsed; destination URLs are only checked by the server
+    InvalidContentType(String), // the content type option is not a valid MIME type
+    BadRequest, // 400: invalid id, expiration, destination URL or missing header
+    Unauthorized, // 401 or 403: missing or invalid API key or owner token
+    NotFound, // 404: the key does not exist, expired or reached its maximum number of reads
+    PayloadTooLarge, // 413: the value is larger than the server maximum value size
+    ServerError, // 500: the server failed to store or read the value
+    UnexpectedStatus(StatusCode),
+    InvalidResponse(String), // the response body is not what the server should return
+    Http(hyper::Error),
+}
+
+impl Error {
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BadRequest => Error::BadRequest,
+            StatusCode::Unauthorized | StatusCode::Forbidden => Error::Unauthorized,
+            StatusCode::NotFound => Error::NotFound,
+            StatusCode::PayloadTooLarge => Error::PayloadTooLarge,
+            StatusCode::InternalServerError => Error::ServerError,
+            status => Error::UnexpectedStatus(status),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidUrl(ref url) => write!(f, "invalid url: {}", url),
+            Error::InvalidContentType(ref content_type) => write!(f, "invalid content type: {}", content_type),
+            Error::BadRequest => write!(f, "bad request"),
+            Error::Unauthorized => write!(f, "unauthorized"),
+            Error::NotFound => write!(f, "not found"),
+            Error::PayloadTooLarge => write!(f, "value too large"),
+            Error::ServerError => write!(f, "server error"),
+            Error::UnexpectedStatus(ref status) => write!(f, "unexpected status: {}", status),
+            Error::InvalidResponse(ref msg) => write!(f, "invalid response: {}", msg),
+            Error::Http(ref err) => write!(f, "http error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidUrl(_) => "invalid url",
+            Error::InvalidContentType(_) => "invalid content type",
+            Error::BadRequest => "bad request",
+            Error::Unauthorized => "unauthorized",
+            Error::NotFound => "not found",
+            Error::PayloadTooLarge => "value too large",
+            Error::ServerError => "server error",
+            Error::UnexpectedStatus(_) => "unexpected status",
+            Error::InvalidResponse(_) => "invalid response",
+            Error::Http(_) => "http error",
+        }
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(err: hyper::Error) -> Self {
+        Error::Http(err)
+    }
+}
@@ -0,0 +1,308 @@
+//! Async client for the qui-vive key-value store and url shortener.
+//!
+//! ```no_run
+//! extern crate qui_vive_client;
+//! extern crate tokio_core;
+//!
+//! use qui_vive_client::{Expiration, KeyOptions, QuiViveClient};
+//!
+//! let mut core = tokio_core::reactor::Core::new().unwrap();
+//! let client = QuiViveClient::new("http://127.0.0.1:8080", &core.handle()).unwrap();
+//!
+//! let options = KeyOptions::new().with_expiration(Expiration::After(3600));
+//! let link = core.run(client.create_key(b"my value".to_vec(), options)).unwrap();
+//! let value = core.run(client.get_key(&link.id)).unwrap();
+//! ```
+
+#[macro_use]
+extern crate hyper;
+
+extern crate futures;
+extern crate tokio_core;
+
+use futures::{Future, Stream};
+use futures::future;
+
+use hyper::{Body, Method, Request, StatusCode, Uri};
+use hyper::client::{Client, Connect, HttpConnector};
+use hyper::header::{Authorization, Bearer, ContentType};
+
+use tokio_core::reactor::Handle;
+
+mod error;
+pub use error::Error;
+
+header! { (QuiViveDstUrl, "QuiVive-DstUrl") => [String] }
+header! { (QuiViveIdParam, "QuiVive-IdParam") => [String] }
+header! { (QuiViveExpiration, "QuiVive-Expiration") => [String] }
+
+/// Expiration of a new key, short URL or invitation.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Expiration {
+    Never,
+    After(u32), // in seconds
+}
+
+impl Expiration {
+    fn header_value(&self) -> String {
+        match *self {
+            Expiration::Never => "0".to_string(), // 0 means no expiration for the server
+            Expiration::After(seconds) => seconds.to_string(),
+        }
+    }
+}
+
+/// Options for `create_key` and `set_key`. The server default expiration is used unless one is set.
+#[derive(Clone, Default, Debug)]
+pub struct KeyOptions {
+    expiration: Option<Expiration>,
+    content_type: Option<String>,
+}
+
+impl KeyOptions {
+    pub fn new() -> Self {
+        KeyOptions::default()
+    }
+
+    pub fn with_expiration(mut self, expiration: Expiration) -> Self {
+        self.expiration = Some(expiration);
+        self
+    }
+
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.to_string());
+        self
+    }
+}
+
+/// Options for `shorten_url`.
+#[derive(Clone, Default, Debug)]
+pub struct UrlOptions {
+    expiration: Option<Expiration>,
+}
+
+impl UrlOptions {
+    pub fn new() -> Self {
+        UrlOptions::default()
+    }
+
+    pub fn with_expiration(mut self, expiration: Expiration) -> Self {
+        self.expiration = Some(expiration);
+        self
+    }
+}
+
+/// Options for `create_invitation`, which always requires a destination URL.
+#[derive(Clone, Debug)]
+pub struct InvitationOptions {
+    dst_url: String,
+    id_param: Option<String>,
+    expiration: Option<Expiration>,
+}
+
+impl InvitationOptions {
+    pub fn new(dst_url: &str) -> Self {
+        InvitationOptions {
+            dst_url: dst_url.to_string(),
+            id_param: None,
+            expiration: None,
+        }
+    }
+
+    /// Name of the query parameter appending the invitation id to the destination URL, which is left as it is otherwise.
+    pub fn with_id_param(mut self, id_param: &str) -> Self {
+        self.id_param = Some(id_param.to_string());
+        self
+    }
+
+    pub fn with_expiration(mut self, expiration: Expiration) -> Self {
+        self.expiration = Some(expiration);
+        self
+    }
+}
+
+/// Id and URL of a key, short URL or invitation created on the server.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Link {
+    pub id: String,
+    pub url: String,
+}
+
+impl Link {
+    fn parse(body: &[u8]) -> Result<Self, Error> {
+        let url = String::from_utf8(body.to_vec())
+            .map_err(|_| Error::InvalidResponse("url is not valid UTF-8".to_string()))?;
+        let url = url.trim().to_string();
+
+        // the id is the last path segment of the returned URL
+        match url.rsplit('/').next() {
+            Some(id) if !id.is_empty() => Ok(Link { id: id.to_string(), url: url.clone() }),
+            _ => Err(Error::InvalidResponse(format!("no id in {}", url))),
+        }
+    }
+}
+
+// ids are a single path segment, so anything but unreserved characters is percent-encoded
+// to keep ids like "a/events" or "a?b" from reaching another route
+fn encode_id(id: &str) -> String {
+    let mut encoded = String::new();
+    for b in id.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(b as char),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+pub type ClientFuture<T> = Box<Future<Item=T, Error=Error>>;
+
+#[derive(Clone)]
+pub struct QuiViveClient<C = HttpConnector> {
+    server_url: String,
+    api_key: Option<String>,
+    client: Client<C, Body>,
+}
+
+impl QuiViveClient<HttpConnector> {
+    /// Creates a client for a plain HTTP server, use `with_client` for HTTPS connectors.
+    pub fn new(server_url: &str, handle: &Handle) -> Result<Self, Error> {
+        QuiViveClient::with_client(server_url, Client::new(handle))
+    }
+}
+
+impl<C: Connect> QuiViveClient<C> {
+    pub fn with_client(server_url: &str, client: Client<C, Body>) -> Result<Self, Error> {
+        let server_url = server_url.trim_end_matches('/').to_string();
+        server_url.parse::<Uri>().map_err(|_| Error::InvalidUrl(server_url.clone()))?;
+
+        Ok(QuiViveClient {
+            server_url: server_url,
+            api_key: None,
+            client: client,
+        })
+    }
+
+    /// Sends the API key as a bearer token, for servers that require authentication.
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    fn new_request(&self, method: Method, path: &str) -> Result<Request, Error> {
+        let url = format!("{}{}", self.server_url, path);
+        let uri = url.parse::<Uri>().map_err(|_| Error::InvalidUrl(url.clone()))?;
+        let mut request = Request::new(method, uri);
+
+        if let Some(ref api_key) = self.api_key {
+            request.headers_mut().set(Authorization(Bearer { token: api_key.clone() }));
+        }
+
+        Ok(request)
+    }
+
+    // sends the request and reads the whole response body, mapping error statuses to typed errors
+    fn send(&self, request: Result<Request, Error>) -> ClientFuture<Vec<u8>> {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => return Box::new(future::err(err)),
+        };
+
+        Box::new(self.client.request(request).from_err().and_then(|response| {
+            let status = response.status();
+            response.body().concat2().from_err().and_then(move |body| {
+                if status == StatusCode::Ok {
+                    Ok(body.to_vec())
+                } else {
+                    Err(Error::from_status(status))
+                }
+            })
+        }))
+    }
+
+    fn key_request(&self, method: Method, path: &str, value: Vec<u8>, options: KeyOptions) -> Result<Request, Error> {
+        let mut request = self.new_request(method, path)?;
+
+        if let Some(expiration) = options.expiration {
+            request.headers_mut().set(QuiViveExpiration(expiration.header_value()));
+        }
+        if let Some(content_type) = options.content_type {
+            let mime = content_type.parse().map_err(|_| Error::InvalidContentType(content_type.clone()))?;
+            request.headers_mut().set(ContentType(mime));
+        }
+
+        request.set_body(value);
+        Ok(request)
+    }
+
+    /// Stores a value under a generated id.
+    pub fn create_key(&self, value: Vec<u8>, options: KeyOptions) -> ClientFuture<Link> {
+        let request = self.key_request(Method::Post, "/key", value, options);
+        Box::new(self.send(request).and_then(|body| Link::parse(&body)))
+    }
+
+    /// Stores a value under the given id, replacing any existing value.
+    pub fn set_key(&self, id: &str, value: Vec<u8>, options: KeyOptions) -> ClientFuture<Link> {
+        let request = self.key_request(Method::Post, &format!("/key/{}", encode_id(id)), value, options);
+        Box::new(self.send(request).and_then(|body| Link::parse(&body)))
+    }
+
+    pub fn get_key(&self, id: &str) -> ClientFuture<Vec<u8>> {
+        let request = self.new_request(Method::Get, &format!("/key/{}", encode_id(id)));
+        self.send(request)
+    }
+
+    /// Deletes a key, which succeeds even if the key does not exist.
+    pub fn delete_key(&self, id: &str) -> ClientFuture<()> {
+        let request = self.new_request(Method::Delete, &format!("/key/{}", encode_id(id)));
+        Box::new(self.send(request).map(|_| ()))
+    }
+
+    /// Gets the description of an entry from the management API, as JSON.
+    pub fn get_entry_info(&self, id: &str) -> ClientFuture<Vec<u8>> {
+        let request = self.new_request(Method::Get, &format!("/api/v1/entries/{}", encode_id(id)));
+        self.send(request)
+    }
+
+    /// Gets the click statistics of an entry from the management API, as JSON.
+    pub fn get_entry_stats(&self, id: &str) -> ClientFuture<Vec<u8>> {
+        let request = self.new_request(Method::Get, &format!("/api/v1/entries/{}/stats", encode_id(id)));
+        self.send(request)
+    }
+
+    /// Changes the expiration of an entry, returning its new description as JSON.
+    pub fn set_entry_ttl(&self, id: &str, expiration: Expiration) -> ClientFuture<Vec<u8>> {
+        let request = self.new_request(Method::Put, &format!("/api/v1/entries/{}/ttl", encode_id(id))).map(|mut request| {
+            request.set_body(expiration.header_value());
+            request
+        });
+        self.send(request)
+    }
+
+    pub fn shorten_url(&self, url: &str, options: UrlOptions) -> ClientFuture<Link> {
+        let request = self.new_request(Method::Post, "/url").map(|mut request| {
+            if let Some(expiration) = options.expiration {
+                request.headers_mut().set(QuiViveExpiration(expiration.header_value()));
+            }
+            request.set_body(url.to_string());
+            request
+        });
+        Box::new(self.send(request).and_then(|body| Link::parse(&body)))
+    }
+
+    /// Creates an invitation link redirecting to the destination URL, with the data readable through the invitation id.
+    pub fn create_invitation(&self, data: Vec<u8>, options: InvitationOptions) -> ClientFuture<Link> {
+        let request = self.new_request(Method::Post, "/inv").map(|mut request| {
+            request.headers_mut().set(QuiViveDstUrl(options.dst_url));
+            if let Some(id_param) = options.id_param {
+                request.headers_mut().set(QuiViveIdParam(id_param));
+            }
+            if let Some(expiration) = options.expiration {
+                request.headers_mut().set(QuiViveExpiration(expiration.header_value()));
+            }
+            request.set_body(data);
+            request
+        });
+        Box::new(self.send(request).and_then(|body| Link::parse(&body)))
+    }
+}
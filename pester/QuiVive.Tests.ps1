@@ -27,6 +27,80 @@ Describe 'create key' {
 		$val = Invoke-RestMethod -Uri $server_url/key/$uuid_key
 		$val | Should -Be "Python is also good"
 	}
+	It 'stores form data as plain text' {
+		$url = (Invoke-RestMethod -Uri $server_url/key -Method 'POST' `
+			-ContentType 'application/x-www-form-urlencoded' -Body "this is a test").trim()
+		$request = Invoke-WebRequest -Uri $url
+		$request.Headers['Content-Type'] | Should -Be 'text/plain; charset=utf-8'
+		$request.Headers.Keys | Should -Not -Contain 'Content-Disposition'
+		$request.Content | Should -Be "this is a test"
+	}
+	It 'creates a one-time key' {
+		$headers = @{
+			"QuiVive-MaxReads" = "1"
+		}
+		$url = (Invoke-RestMethod -Uri $server_url/key -Method 'POST' `
+			-ContentType 'text/plain' -Body "burn after reading" -Headers $headers).trim()
+		$val = Invoke-RestMethod -Uri $url
+		$val | Should -Be "burn after reading"
+		{ Invoke-RestMethod -Uri $url } | Should -Throw
+	}
+	It 'hides the value of a one-time key from the management API' {
+		$headers = @{
+			"QuiVive-MaxReads" = "1"
+		}
+		$url = (Invoke-RestMethod -Uri $server_url/key -Method 'POST' `
+			-ContentType 'text/plain' -Body "burn after reading" -Headers $headers).trim()
+		$id = $url.Split('/')[-1]
+		$info = Invoke-RestMethod -Uri $server_url/api/v1/entries/$id
+		$info.max_reads | Should -Be 1
+		$info.PSObject.Properties.Name | Should -Not -Contain 'value'
+		$val = Invoke-RestMethod -Uri $url
+		$val | Should -Be "burn after reading"
+		{ Invoke-RestMethod -Uri $url } | Should -Throw
+	}
+	It 'rejects an invalid maximum number of reads' {
+		$headers = @{
+			"QuiVive-MaxReads" = "once"
+		}
+		{ Invoke-RestMethod -Uri $server_url/key -Method 'POST' `
+			-ContentType 'text/plain' -Body "burn after reading" -Headers $headers } | Should -Throw
+	}
+	It 'requires the owner token to overwrite or delete an owned key' {
+		$request = Invoke-WebRequest -Uri $server_url/key -Method 'POST' `
+			-ContentType 'text/plain' -Body "this is a test" -Headers @{ "QuiVive-Owned" = "true" }
+		$url = $request.Content.trim()
+		$owner_token = $request.Headers['QuiVive-OwnerToken'] | Select-Object -First 1
+		$owner_token | Should -Not -BeNullOrEmpty
+		$wrong_headers = @{ "QuiVive-OwnerToken" = "wrong" }
+		{ Invoke-RestMethod -Uri $url -Method 'POST' `
+			-ContentType 'text/plain' -Body "overwritten" } | Should -Throw '*403*'
+		{ Invoke-RestMethod -Uri $url -Method 'POST' -Headers $wrong_headers `
+			-ContentType 'text/plain' -Body "overwritten" } | Should -Throw '*403*'
+		{ Invoke-RestMethod -Uri $url -Method 'DELETE' } | Should -Throw '*403*'
+		{ Invoke-RestMethod -Uri $url -Method 'DELETE' -Headers $wrong_headers } | Should -Throw '*403*'
+		$val = Invoke-RestMethod -Uri $url
+		$val | Should -Be "this is a test"
+		Invoke-RestMethod -Uri $url -Method 'DELETE' -Headers @{ "QuiVive-OwnerToken" = $owner_token }
+		{ Invoke-RestMethod -Uri $url } | Should -Throw '*404*'
+	}
+	It 'requires the owner token to retarget an owned short URL' {
+		$long_url = "https://wayk.devolutions.net/"
+		$request = Invoke-WebRequest -Uri $server_url/url -Method 'POST' `
+			-ContentType 'text/plain' -Body $long_url -Headers @{ "QuiVive-Owned" = "true" }
+		$short_url = $request.Content.trim()
+		$id = $short_url.Split('/')[-1]
+		$owner_token = $request.Headers['QuiVive-OwnerToken'] | Select-Object -First 1
+		{ Invoke-RestMethod -Uri $server_url/url/$id -Method 'PUT' `
+			-ContentType 'text/plain' -Body "https://contoso.com/" } | Should -Throw '*403*'
+		{ Invoke-RestMethod -Uri $server_url/url/$id -Method 'PUT' -Headers @{ "QuiVive-OwnerToken" = "wrong" } `
+			-ContentType 'text/plain' -Body "https://contoso.com/" } | Should -Throw '*403*'
+		Invoke-RestMethod -Uri $server_url/url/$id -Method 'PUT' -Headers @{ "QuiVive-OwnerToken" = $owner_token } `
+			-ContentType 'text/plain' -Body "https://contoso.com/"
+		$request = Invoke-WebRequest -Uri $short_url
+		$redirect_url = $request.BaseResponse.RequestMessage.RequestUri.AbsoluteUri
+		$redirect_url | Should -Be "https://contoso.com/"
+	}
 	It 'create short URL' {
 		$long_url = "https://wayk.devolutions.net/"
 		$short_url = (Invoke-RestMethod -Uri $server_url/url -Method 'POST' `
@@ -35,6 +109,31 @@ Describe 'create key' {
 		$redirect_url = $request.BaseResponse.RequestMessage.RequestUri.AbsoluteUri
 		$redirect_url | Should -Be $long_url
 	}
+	It 'does not overwrite a taken short URL alias' {
+		$alias = 'pester-' + [guid]::NewGuid().ToString('N').Substring(0, 8)
+		$long_url = "https://wayk.devolutions.net/"
+		$short_url = (Invoke-RestMethod -Uri $server_url/url/$alias -Method 'POST' `
+			-ContentType 'text/plain' -Body $long_url).trim()
+		{ Invoke-RestMethod -Uri $server_url/url/$alias -Method 'POST' `
+			-ContentType 'text/plain' -Body "https://contoso.com/" } | Should -Throw '*409*'
+		{ Invoke-RestMethod -Uri $server_url/url/metrics -Method 'POST' `
+			-ContentType 'text/plain' -Body $long_url } | Should -Throw '*400*'
+		$request = Invoke-WebRequest -Uri $short_url
+		$redirect_url = $request.BaseResponse.RequestMessage.RequestUri.AbsoluteUri
+		$redirect_url | Should -Be $long_url
+	}
+	It 'does not overwrite a short URL alias with a key' {
+		$alias = 'pester-' + [guid]::NewGuid().ToString('N').Substring(0, 8)
+		$long_url = "https://wayk.devolutions.net/"
+		$short_url = (Invoke-RestMethod -Uri $server_url/url/$alias -Method 'POST' `
+			-ContentType 'text/plain' -Body $long_url).trim()
+		$short_url | Should -Be $server_url/$alias
+		{ Invoke-RestMethod -Uri $server_url/key/$alias -Method 'POST' `
+			-ContentType 'text/plain' -Body "this is a test" } | Should -Throw '*409*'
+		$request = Invoke-WebRequest -Uri $short_url
+		$redirect_url = $request.BaseResponse.RequestMessage.RequestUri.AbsoluteUri
+		$redirect_url | Should -Be $long_url
+	}
 	It 'create invitation link' {
 		$dst_url = "https://wayk.devolutions.net/invitation"
 		$inv_data = '{"meeting":"master plan","organizer":"ceo@contoso.com"}'
@@ -54,3 +153,49 @@ Describe 'create key' {
 		$redirect_url | Should -BeLike "$dst_url?id*"
 	}
 }
+Describe 'authentication' -Skip:(-not $Env:QUI_VIVE_WRITE_KEY) {
+	BeforeAll {
+		# requires a server with one api key per scope, for instance:
+		# API_KEYS="$QUI_VIVE_READ_KEY:read $QUI_VIVE_WRITE_KEY:write $QUI_VIVE_DELETE_KEY:delete"
+		$server_url = $Env:QUI_VIVE_URL
+		$read_headers = @{ "Authorization" = "Bearer $Env:QUI_VIVE_READ_KEY" }
+		$write_headers = @{ "Authorization" = "Bearer $Env:QUI_VIVE_WRITE_KEY" }
+		$delete_headers = @{ "QuiVive-ApiKey" = $Env:QUI_VIVE_DELETE_KEY }
+		$unknown_headers = @{ "Authorization" = "Bearer unknown" }
+	}
+	It 'requires the write scope to create a key' {
+		{ Invoke-RestMethod -Uri $server_url/key -Method 'POST' `
+			-ContentType 'text/plain' -Body "this is a test" } | Should -Throw '*401*'
+		{ Invoke-RestMethod -Uri $server_url/key -Method 'POST' -Headers $unknown_headers `
+			-ContentType 'text/plain' -Body "this is a test" } | Should -Throw '*401*'
+		{ Invoke-RestMethod -Uri $server_url/key -Method 'POST' -Headers $read_headers `
+			-ContentType 'text/plain' -Body "this is a test" } | Should -Throw '*403*'
+		$url = (Invoke-RestMethod -Uri $server_url/key -Method 'POST' -Headers $write_headers `
+			-ContentType 'text/plain' -Body "this is a test").trim()
+		$val = Invoke-RestMethod -Uri $url
+		$val | Should -Be "this is a test"
+	}
+	It 'requires the read scope to inspect a key' {
+		$url = (Invoke-RestMethod -Uri $server_url/key -Method 'POST' -Headers $write_headers `
+			-ContentType 'text/plain' -Body "this is a test").trim()
+		$id = $url.Split('/')[-1]
+		{ Invoke-RestMethod -Uri $server_url/api/v1/entries/$id } | Should -Throw '*401*'
+		{ Invoke-RestMethod -Uri $server_url/api/v1/entries/$id -Headers $write_headers } | Should -Throw '*403*'
+		$info = Invoke-RestMethod -Uri $server_url/api/v1/entries/$id -Headers $read_headers
+		$info.kind | Should -Be "key"
+	}
+	It 'requires the read scope to scrape metrics' {
+		{ Invoke-RestMethod -Uri $server_url/metrics } | Should -Throw '*401*'
+		{ Invoke-RestMethod -Uri $server_url/metrics -Headers $write_headers } | Should -Throw '*403*'
+		$metrics = Invoke-RestMethod -Uri $server_url/metrics -Headers $read_headers
+		$metrics | Should -BeLike '*quivive_creations_total*'
+	}
+	It 'requires the delete scope to delete a key' {
+		$url = (Invoke-RestMethod -Uri $server_url/key -Method 'POST' -Headers $write_headers `
+			-ContentType 'text/plain' -Body "this is a test").trim()
+		{ Invoke-RestMethod -Uri $url -Method 'DELETE' } | Should -Throw '*401*'
+		{ Invoke-RestMethod -Uri $url -Method 'DELETE' -Headers $write_headers } | Should -Throw '*403*'
+		Invoke-RestMethod -Uri $url -Method 'DELETE' -Headers $delete_headers
+		{ Invoke-RestMethod -Uri $url } | Should -Throw '*404*'
+	}
+}
@@ -0,0 +1,221 @@
+use clap::ArgMatches;
+use serde_json;
+use time;
+
+use crate::QuiViveConfig;
+use crate::api::{EntryInfo, StatsInfo};
+use crate::cache::{self, QuiViveCache, QuiViveEntry, Update};
+use crate::{new_cache, open_redis_store};
+use crate::service::get_timestamp;
+
+/// Maintenance subcommand, run directly against the configured cache backend.
+#[derive(Clone, PartialEq, Debug)]
+pub enum AdminCommand {
+    List { filter: EntryFilter },
+    Inspect { id: String },
+    Purge { filter: EntryFilter, dry_run: bool },
+    Count { filter: EntryFilter },
+    SetTtl { id: String, ttl: Option<u32> },
+    Migrate { delete_legacy: bool },
+}
+
+/// Selects entries by id prefix, kind and age.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct EntryFilter {
+    pub prefix: String,
+    pub kind: Option<String>,
+    pub older_than: Option<u32>, // in seconds since creation
+}
+
+impl EntryFilter {
+    fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        Ok(EntryFilter {
+            prefix: matches.value_of("prefix").unwrap_or_default().to_string(),
+            kind: matches.value_of("kind").map(String::from),
+            older_than: match matches.value_of("older-than") {
+                Some(older_than) => Some(parse_duration(older_than)?),
+                None => None,
+            },
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.prefix.is_empty() && self.kind.is_none() && self.older_than.is_none()
+    }
+
+    fn matches(&self, entry: &QuiViveEntry, now: u32) -> bool {
+        self.kind.as_ref().map_or(true, |kind| entry.kind == *kind) &&
+            self.older_than.map_or(true, |older_than| entry.created.saturating_add(older_than) <= now)
+    }
+}
+
+/// Parses a duration like '90', '45s', '15m', '12h', '30d' or '2w', in seconds.
+pub fn parse_duration(value: &str) -> Result<u32, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(format!("invalid duration unit in {} (s, m, h, d or w)", value)),
+    };
+
+    number.parse::<u32>().ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid duration: {}", value))
+}
+
+fn format_timestamp(timestamp: Option<u32>) -> String {
+    match timestamp {
+        Some(timestamp) => time::at_utc(time::Timespec::new(timestamp as i64, 0)).rfc3339().to_string(),
+        None => "never".to_string(),
+    }
+}
+
+impl AdminCommand {
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        match matches.subcommand() {
+            ("list", Some(matches)) => Ok(AdminCommand::List {
+                filter: EntryFilter::from_matches(matches)?,
+            }),
+            ("inspect", Some(matches)) => Ok(AdminCommand::Inspect {
+                id: matches.value_of("id").unwrap().to_string(),
+            }),
+            ("purge", Some(matches)) => {
+                let filter = EntryFilter::from_matches(matches)?;
+                if filter.is_empty() {
+                    // purging every entry by mistake is too easy otherwise
+                    return Err("purge requires --prefix, --kind or --older-than".to_string());
+                }
+                Ok(AdminCommand::Purge {
+                    filter: filter,
+                    dry_run: matches.is_present("dry-run"),
+                })
+            }
+            ("count", Some(matches)) => Ok(AdminCommand::Count {
+                filter: EntryFilter::from_matches(matches)?,
+            }),
+            ("ttl-set", Some(matches)) => {
+                let ttl = parse_duration(matches.value_of("ttl").unwrap())?;
+                Ok(AdminCommand::SetTtl {
+                    id: matches.value_of("id").unwrap().to_string(),
+                    ttl: if ttl == 0 { None } else { Some(ttl) },
+                })
+            }
+            ("migrate", Some(matches)) => Ok(AdminCommand::Migrate {
+                delete_legacy: matches.is_present("delete-legacy"),
+            }),
+            _ => Err("missing admin subcommand (list, inspect, purge, count, ttl-set or migrate)".to_string()),
+        }
+    }
+
+    /// Runs the subcommand against the cache backend of the configuration, writing its result to standard output.
+    pub fn run(&self, cfg: &QuiViveConfig) -> Result<(), String> {
+        if let AdminCommand::Migrate { delete_legacy } = *self {
+            return migrate(cfg, delete_legacy);
+        }
+
+        // the management API of the server cannot list entries, nor update owned entries without their owner token
+        if cfg.cache_type.as_ref().map_or("memory", |x| x.as_str()) == "memory" {
+            return Err("the memory cache only lives inside the server process, \
+                admin subcommands require the redis or file cache type".to_string());
+        }
+
+        let cache = new_cache(cfg).map_err(|err| format!("failed to open the cache: {}", err))?;
+        let now = get_timestamp();
+
+        match *self {
+            AdminCommand::List { ref filter } => {
+                for entry in find_entries(&cache, filter, now)? {
+                    println!("{}\t{}\t{}\t{}", entry.id, entry.kind,
+                        format_timestamp(Some(entry.created)), format_timestamp(entry.expiration()));
+                }
+            }
+            AdminCommand::Inspect { ref id } => {
+                let entry = cache.get(id.clone()).map_err(|err| err.to_string())?
+                    .ok_or_else(|| format!("{} not found", id))?;
+                let stats = cache.get_stats(id.clone()).map_err(|err| err.to_string())?;
+
+                let info = json!({
+                    "entry": EntryInfo::new(&entry, now),
+                    "stats": stats.as_ref().map(StatsInfo::new),
+                });
+                println!("{}", serde_json::to_string_pretty(&info).unwrap_or_default());
+            }
+            AdminCommand::Purge { ref filter, dry_run } => {
+                let entries = find_entries(&cache, filter, now)?;
+                for entry in &entries {
+                    if !dry_run {
+                        cache.remove(entry.id.clone()).map_err(|err| err.to_string())?;
+                    }
+                    println!("{}", entry.id);
+                }
+                eprintln!("{} {} entries", if dry_run { "would purge" } else { "purged" }, entries.len());
+            }
+            AdminCommand::Count { ref filter } => {
+                println!("{}", find_entries(&cache, filter, now)?.len());
+            }
+            AdminCommand::SetTtl { ref id, ttl } => {
+                let expiration = ttl.map(|x| x as usize);
+
+                // compare-and-swap such that reads and updates made by a running server are kept
+                let entry = cache.update(id.clone(), |existing| match existing {
+                    Some(entry) => {
+                        let mut entry = entry.clone();
+                        entry.expires = ttl.map_or(0, |ttl| now + ttl);
+                        Update::Put(entry, expiration)
+                    }
+                    None => Update::Keep(()),
+                }).map_err(|err| err.to_string())?
+                    .map_err(|_| format!("{} not found", id))?;
+
+                // click statistics expire along with the entry
+                cache.touch_stats(id, expiration).map_err(|err| err.to_string())?;
+
+                println!("{}\t{}", entry.id, format_timestamp(entry.expiration()));
+            }
+            AdminCommand::Migrate { .. } => unreachable!(),
+        }
+
+        Ok(())
+    }
+}
+
+// older versions only supported redis, so there is nothing to migrate with other cache types
+fn migrate(cfg: &QuiViveConfig, delete_legacy: bool) -> Result<(), String> {
+    if cfg.cache_type.as_ref().map(String::as_str) != Some("redis") {
+        return Err("only redis caches written by qui-vive 0.3 and earlier need to be migrated".to_string());
+    }
+
+    let store = open_redis_store(cfg).map_err(|err| format!("failed to open the cache: {}", err))?;
+    let migrated = cache::migrate_legacy_records(&store, delete_legacy).map_err(|err| err.to_string())?;
+
+    println!("{}", migrated);
+    eprintln!("copied {} records{}", migrated, if delete_legacy { " and deleted the legacy ones" } else { "" });
+    Ok(())
+}
+
+fn find_entries(cache: &QuiViveCache, filter: &EntryFilter, now: u32) -> Result<Vec<QuiViveEntry>, String> {
+    let prefix = format!("{}{}", cache::ENTRY_PREFIX, filter.prefix);
+    let mut entries = Vec::new();
+
+    for key in cache.store().scan_prefix(&prefix).map_err(|err| err.to_string())? {
+        let id = key[cache::ENTRY_PREFIX.len()..].to_string();
+
+        // the entry can expire or be deleted between the scan and the read
+        match cache.get(id) {
+            Ok(Some(ref entry)) if filter.matches(entry, now) => entries.push(entry.clone()),
+            Ok(_) => {}
+            Err(err) => warn!("skipping unreadable entry {}: {}", key, err),
+        }
+    }
+
+    entries.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.id.cmp(&b.id)));
+    Ok(entries)
+}
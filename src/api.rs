@@ -0,0 +1,70 @@
+use crate::cache::{QuiViveEntry, QuiViveStats, Click};
+
+/// JSON representation of an entry returned by the management API.
+#[derive(Serialize, Debug)]
+pub struct EntryInfo {
+    pub id: String,
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    pub content_type: Option<String>,
+    pub url: Option<String>,
+    pub redirect_status: Option<u16>,
+    pub created: u32,
+    pub expires: Option<u32>,
+    pub ttl: Option<u32>,
+    pub max_reads: Option<u32>,
+    pub reads: u32,
+    pub owned: bool,
+}
+
+impl EntryInfo {
+    pub fn new(entry: &QuiViveEntry, now: u32) -> Self {
+        EntryInfo {
+            id: entry.id.clone(),
+            kind: entry.kind.clone(),
+            value: Some(entry.val.clone()),
+            encoding: if entry.encoding.is_empty() { None } else { Some(entry.encoding.clone()) },
+            content_type: if entry.content_type.is_empty() { None } else { Some(entry.content_type.clone()) },
+            url: if entry.url.is_empty() { None } else { Some(entry.url.clone()) },
+            redirect_status: if entry.redirect_status == 0 { None } else { Some(entry.redirect_status) },
+            created: entry.created,
+            expires: entry.expiration(),
+            ttl: entry.ttl(now),
+            max_reads: if entry.max_reads == 0 { None } else { Some(entry.max_reads) },
+            reads: entry.reads,
+            owned: entry.is_owned(),
+        }
+    }
+
+    /// Leaves out the value, for clients that can see changes without reading the entry.
+    pub fn without_value(mut self) -> Self {
+        self.value = None;
+        self.encoding = None;
+        self
+    }
+}
+
+/// JSON representation of the click statistics of an entry.
+#[derive(Serialize, Debug)]
+pub struct StatsInfo {
+    pub id: String,
+    pub hits: u64,
+    pub first_hit: Option<u32>,
+    pub last_hit: Option<u32>,
+    pub clicks: Vec<Click>,
+}
+
+impl StatsInfo {
+    pub fn new(stats: &QuiViveStats) -> Self {
+        StatsInfo {
+            id: stats.id.clone(),
+            hits: stats.hits,
+            first_hit: if stats.first_hit == 0 { None } else { Some(stats.first_hit) },
+            last_hit: if stats.last_hit == 0 { None } else { Some(stats.last_hit) },
+            clicks: stats.recent_clicks(),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+
+use hyper::{Method, StatusCode};
+use hyper::header::{Authorization, Bearer};
+use hyper::server::{Request, Response};
+
+header! { (QuiViveApiKey, "QuiVive-ApiKey") => [String] }
+header! { (WwwAuthenticate, "WWW-Authenticate") => [String] }
+
+/// Placeholder printed instead of secrets, which is never accepted as a secret itself.
+pub const REDACTED: &str = "<redacted>";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scope {
+    Read,
+    Write,
+    Delete,
+}
+
+const ALL_SCOPES: [Scope; 3] = [Scope::Read, Scope::Write, Scope::Delete];
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+            Scope::Delete => "delete",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "read" => Ok(Scope::Read),
+            "write" => Ok(Scope::Write),
+            "delete" => Ok(Scope::Delete),
+            _ => Err(format!("invalid scope: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub token: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiKey {
+    /// Parses a `<token>[:<scope>[,<scope>...]]` definition, granting all scopes when none are listed.
+    pub fn parse(s: &str) -> Result<ApiKey, String> {
+        let mut parts = s.trim().splitn(2, ':');
+        let token = parts.next().unwrap_or("").to_string();
+
+        if token.is_empty() {
+            return Err("empty api key".to_string());
+        }
+
+        // printed configurations must not install a publicly known token when they are loaded back
+        if token == REDACTED {
+            return Err(format!("{} is not a valid api key", REDACTED));
+        }
+
+        let scopes = match parts.next() {
+            Some(scopes) => scopes.split(',').map(Scope::from_str).collect::<Result<Vec<_>, _>>()?,
+            None => ALL_SCOPES.to_vec(),
+        };
+
+        Ok(ApiKey { token: token, scopes: scopes })
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// Loads api keys from a file with one key definition per line, ignoring empty lines and '#' comments.
+pub fn load_api_key_file(path: &str) -> io::Result<Vec<ApiKey>> {
+    let file = File::open(path)?;
+    let mut api_keys = Vec::new();
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let api_key = ApiKey::parse(line)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        api_keys.push(api_key);
+    }
+
+    Ok(api_keys)
+}
+
+/// Returns the scope required for a request, or None for anonymous requests.
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    match *method {
+        Method::Post | Method::Put | Method::Patch => Some(Scope::Write),
+        Method::Delete => Some(Scope::Delete),
+        Method::Get if path.starts_with("/api/") => Some(Scope::Read),
+        // metrics reveal the activity of the service
+        Method::Get if path == "/metrics" => Some(Scope::Read),
+        // events reveal when a key is set, and are not limited by its maximum number of reads
+        Method::Get if path.starts_with("/key/") && path.ends_with("/events") => Some(Scope::Read),
+        _ => None,
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn request_token(request: &Request) -> Option<String> {
+    if let Some(authorization) = request.headers().get::<Authorization<Bearer>>() {
+        return Some(authorization.token.clone());
+    }
+    request.headers().get::<QuiViveApiKey>().map(|x| x.to_string())
+}
+
+/// Checks that the request carries an api key with the given scope. Authentication
+/// is disabled when no api keys are configured.
+pub fn authorize(api_keys: &[ApiKey], request: &Request, scope: Scope) -> Result<(), Response> {
+    if api_keys.is_empty() {
+        return Ok(());
+    }
+
+    let token = match request_token(request) {
+        Some(token) => token,
+        None => {
+            return Err(Response::new()
+                .with_status(StatusCode::Unauthorized)
+                .with_header(WwwAuthenticate("Bearer".to_string())));
+        }
+    };
+
+    let api_key = api_keys.iter()
+        .find(|api_key| constant_time_eq(api_key.token.as_bytes(), token.as_bytes()));
+
+    match api_key {
+        Some(api_key) if api_key.has_scope(scope) => Ok(()),
+        Some(_) => Err(Response::new().with_status(StatusCode::Forbidden)),
+        None => {
+            Err(Response::new()
+                .with_status(StatusCode::Unauthorized)
+                .with_header(WwwAuthenticate("Bearer error=\"invalid_token\"".to_string())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_api_keys() {
+        let api_key = ApiKey::parse(" s3cr3t ").unwrap();
+        assert_eq!(api_key.token, "s3cr3t");
+        assert_eq!(api_key.scopes, ALL_SCOPES.to_vec());
+
+        let api_key = ApiKey::parse("s3cr3t:Read, write").unwrap();
+        assert_eq!(api_key.scopes, vec![Scope::Read, Scope::Write]);
+        assert!(!api_key.has_scope(Scope::Delete));
+    }
+
+    #[test]
+    fn rejects_malformed_api_keys() {
+        assert!(ApiKey::parse("").is_err());
+        assert!(ApiKey::parse(":read").is_err());
+        assert!(ApiKey::parse("s3cr3t:").is_err());
+        assert!(ApiKey::parse("s3cr3t:read,").is_err());
+        assert!(ApiKey::parse("s3cr3t:admin").is_err());
+        assert!(ApiKey::parse(REDACTED).is_err());
+        assert!(ApiKey::parse(&format!("{}:read", REDACTED)).is_err());
+    }
+
+    #[test]
+    fn requires_scopes() {
+        assert_eq!(required_scope(&Method::Post, "/key"), Some(Scope::Write));
+        assert_eq!(required_scope(&Method::Put, "/url/TwfdpHQJC"), Some(Scope::Write));
+        assert_eq!(required_scope(&Method::Put, "/api/v1/entries/TwfdpHQJC/ttl"), Some(Scope::Write));
+        assert_eq!(required_scope(&Method::Delete, "/key/TwfdpHQJC"), Some(Scope::Delete));
+        assert_eq!(required_scope(&Method::Get, "/api/v1/entries/TwfdpHQJC"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::Get, "/key/TwfdpHQJC/events"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::Get, "/metrics"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::Get, "/key/TwfdpHQJC"), None);
+        assert_eq!(required_scope(&Method::Get, "/TwfdpHQJC"), None);
+        assert_eq!(required_scope(&Method::Get, "/health"), None);
+    }
+
+    fn request(token: Option<&str>) -> Request {
+        let mut request = Request::new(Method::Get, "/api/v1/entries/TwfdpHQJC".parse().unwrap());
+        if let Some(token) = token {
+            request.headers_mut().set(Authorization(Bearer { token: token.to_string() }));
+        }
+        request
+    }
+
+    #[test]
+    fn authorizes_scopes() {
+        let api_keys = vec![ApiKey::parse("r34d:read").unwrap(), ApiKey::parse("wr1t3:write").unwrap()];
+        let status = |token, scope| authorize(&api_keys, &request(token), scope).err().map(|x| x.status());
+
+        assert_eq!(status(Some("r34d"), Scope::Read), None);
+        assert_eq!(status(Some("wr1t3"), Scope::Read), Some(StatusCode::Forbidden));
+        assert_eq!(status(Some("r34d"), Scope::Write), Some(StatusCode::Forbidden));
+        assert_eq!(status(Some("unknown"), Scope::Read), Some(StatusCode::Unauthorized));
+        assert_eq!(status(None, Scope::Read), Some(StatusCode::Unauthorized));
+
+        // without api keys, authentication is disabled
+        assert!(authorize(&[], &request(None), Scope::Delete).is_ok());
+    }
+}
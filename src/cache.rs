@@ -0,0 +1,472 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+use base64;
+
+use crate::auth::constant_time_eq;
+use crate::notify::{Listener, Notifier};
+use crate::service::get_timestamp;
+use crate::store::{RedisStore, Store, StoreError};
+
+pub const KIND_KEY: &str = "key";
+pub const KIND_URL: &str = "url";
+pub const KIND_INV: &str = "inv";
+
+pub const ENCODING_BASE64: &str = "base64";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuiViveEntry {
+    pub id: String,
+    pub kind: String,
+    pub val: String,
+    pub url: String,
+    pub content_type: String,
+    pub encoding: String, // empty for UTF-8 values, 'base64' for binary values
+    pub created: u32, // creation timestamp, in seconds
+    pub expires: u32, // expiration timestamp, in seconds (0 means no expiration)
+    pub max_reads: u32, // maximum number of reads (0 means unlimited)
+    pub reads: u32,
+    pub owner_token: String, // secret required to manage the entry (empty means no owner)
+    pub redirect_status: u16, // redirect status code (0 means the default for the entry kind)
+}
+
+impl QuiViveEntry {
+    pub fn new(id: String, kind: &str, val: String, url: String, expiration: Option<usize>) -> Self {
+        let created = get_timestamp();
+        QuiViveEntry {
+            id: id,
+            kind: kind.to_string(),
+            val: val,
+            url: url,
+            content_type: "".to_string(),
+            encoding: "".to_string(),
+            created: created,
+            expires: expiration.map_or(0, |x| created + x as u32),
+            max_reads: 0,
+            reads: 0,
+            owner_token: "".to_string(),
+            redirect_status: 0,
+        }
+    }
+
+    pub fn with_redirect_status(mut self, redirect_status: Option<u16>) -> Self {
+        self.redirect_status = redirect_status.unwrap_or(0);
+        self
+    }
+
+    pub fn with_owner_token(mut self, owner_token: Option<String>) -> Self {
+        self.owner_token = owner_token.unwrap_or_default();
+        self
+    }
+
+    pub fn is_owned(&self) -> bool {
+        !self.owner_token.is_empty()
+    }
+
+    /// Checks if the given token allows managing the entry, which is always the case for entries without an owner.
+    pub fn is_owned_by(&self, owner_token: Option<&str>) -> bool {
+        if !self.is_owned() {
+            return true;
+        }
+        owner_token.map_or(false, |token| constant_time_eq(self.owner_token.as_bytes(), token.as_bytes()))
+    }
+
+    pub fn with_max_reads(mut self, max_reads: u32) -> Self {
+        self.max_reads = max_reads;
+        self
+    }
+
+    pub fn with_content(mut self, data: Vec<u8>, content_type: Option<String>) -> Self {
+        match String::from_utf8(data) {
+            Ok(value) => {
+                self.val = value;
+                self.encoding = "".to_string();
+            }
+            Err(err) => {
+                // binary values cannot be stored as-is in string fields
+                self.val = base64::encode(err.as_bytes());
+                self.encoding = ENCODING_BASE64.to_string();
+            }
+        }
+        self.content_type = content_type.unwrap_or_default();
+        self
+    }
+
+    pub fn data(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        if self.encoding == ENCODING_BASE64 {
+            base64::decode(&self.val)
+        } else {
+            Ok(self.val.clone().into_bytes())
+        }
+    }
+
+    pub fn expiration(&self) -> Option<u32> {
+        if self.expires == 0 { None } else { Some(self.expires) }
+    }
+
+    pub fn ttl(&self, now: u32) -> Option<u32> {
+        self.expiration().map(|expires| expires.saturating_sub(now))
+    }
+}
+
+const MAX_RECENT_CLICKS: usize = 20;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Click {
+    pub timestamp: u32,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuiViveStats {
+    pub id: String,
+    pub hits: u64,
+    pub first_hit: u32, // timestamp of the first click, in seconds (0 means never)
+    pub last_hit: u32, // timestamp of the last click, in seconds (0 means never)
+    pub clicks: String, // JSON array of the most recent clicks, newest first
+}
+
+impl QuiViveStats {
+    pub fn new(id: String) -> Self {
+        QuiViveStats {
+            id: id,
+            hits: 0,
+            first_hit: 0,
+            last_hit: 0,
+            clicks: "[]".to_string(),
+        }
+    }
+
+    pub fn recent_clicks(&self) -> Vec<Click> {
+        serde_json::from_str(&self.clicks).unwrap_or_default()
+    }
+
+    fn add_click(&mut self, click: Click) {
+        self.hits += 1;
+        if self.first_hit == 0 {
+            self.first_hit = click.timestamp;
+        }
+        self.last_hit = click.timestamp;
+
+        let mut clicks = self.recent_clicks();
+        clicks.insert(0, click);
+        clicks.truncate(MAX_RECENT_CLICKS);
+        self.clicks = serde_json::to_string(&clicks).unwrap_or_else(|_| "[]".to_string());
+    }
+}
+
+// key layout in the store, the prefixes allow scanning each kind of record
+pub const ENTRY_PREFIX: &str = "entry:";
+pub const STATS_PREFIX: &str = "stats:";
+pub const COUNTER_PREFIX: &str = "counter:";
+
+// versions before the Store trait kept entries and stats in redis hashes named '<model>:<id>'
+const LEGACY_ENTRY_MODEL: &str = "QuiVive";
+const LEGACY_STATS_MODEL: &str = "QuiViveStats";
+
+// compare-and-swap loops give up after this many concurrent modifications
+const MAX_SWAP_ATTEMPTS: usize = 100;
+
+pub fn entry_key(id: &str) -> String {
+    format!("{}{}", ENTRY_PREFIX, id)
+}
+
+pub fn stats_key(id: &str) -> String {
+    format!("{}{}", STATS_PREFIX, id)
+}
+
+fn counter_key(name: &str) -> String {
+    format!("{}{}", COUNTER_PREFIX, name)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, StoreError> {
+    Ok(serde_json::to_string(value)?)
+}
+
+fn from_json<T: DeserializeOwned>(value: &str) -> Result<T, StoreError> {
+    Ok(serde_json::from_str(value)?)
+}
+
+fn to_ttl(expiration: Option<usize>) -> Option<u32> {
+    expiration.map(|x| x as u32)
+}
+
+fn legacy_field<T: FromStr + Default>(fields: &HashMap<String, String>, name: &str) -> T {
+    fields.get(name).and_then(|value| value.parse::<T>().ok()).unwrap_or_default()
+}
+
+// the oldest versions only stored the id, value and url, so missing fields get defaults
+fn legacy_entry(fields: &HashMap<String, String>, ttl: Option<u32>) -> Result<String, StoreError> {
+    let now = get_timestamp();
+    let url: String = legacy_field(fields, "url");
+    let kind = match fields.get("kind") {
+        Some(kind) => kind.clone(),
+        None if url.is_empty() => KIND_KEY.to_string(),
+        None => KIND_URL.to_string(),
+    };
+
+    to_json(&QuiViveEntry {
+        id: legacy_field(fields, "id"),
+        kind: kind,
+        val: legacy_field(fields, "val"),
+        url: url,
+        content_type: legacy_field(fields, "content_type"),
+        encoding: legacy_field(fields, "encoding"),
+        created: fields.get("created").and_then(|x| x.parse().ok()).unwrap_or(now),
+        expires: fields.get("expires").and_then(|x| x.parse().ok()).unwrap_or_else(|| ttl.map_or(0, |ttl| now + ttl)),
+        max_reads: legacy_field(fields, "max_reads"),
+        reads: legacy_field(fields, "reads"),
+        owner_token: legacy_field(fields, "owner_token"),
+        redirect_status: legacy_field(fields, "redirect_status"),
+    })
+}
+
+fn legacy_stats(fields: &HashMap<String, String>, _ttl: Option<u32>) -> Result<String, StoreError> {
+    to_json(&QuiViveStats {
+        id: legacy_field(fields, "id"),
+        hits: legacy_field(fields, "hits"),
+        first_hit: legacy_field(fields, "first_hit"),
+        last_hit: legacy_field(fields, "last_hit"),
+        clicks: fields.get("clicks").cloned().unwrap_or_else(|| "[]".to_string()),
+    })
+}
+
+/// Copies the entries and stats stored by versions before the Store trait to the current key layout,
+/// returning the number of copied records. Entries keep their time to live, and the ones without
+/// expiration would otherwise never be read nor removed. The old records are only deleted with
+/// `delete_legacy`, once no older instance serves them anymore.
+pub fn migrate_legacy_records(store: &RedisStore, delete_legacy: bool) -> Result<usize, StoreError> {
+    let entries = store.migrate_hashes(LEGACY_ENTRY_MODEL, ENTRY_PREFIX, legacy_entry, delete_legacy)?;
+    let stats = store.migrate_hashes(LEGACY_STATS_MODEL, STATS_PREFIX, legacy_stats, delete_legacy)?;
+    Ok(entries + stats)
+}
+
+/// Result of the function given to `QuiViveCache::update`.
+pub enum Update<T> {
+    Put(QuiViveEntry, Option<usize>), // replaces the entry, expiring after the given number of seconds
+    Keep(T), // leaves the entry as it is
+}
+
+/// Reason why an owner-checked change left an entry as it is.
+#[derive(Debug, PartialEq)]
+pub enum Refused<T> {
+    Missing, // the entry does not exist
+    NotOwner, // the owner token does not match the entry
+    Kept(T), // the value given with `Update::Keep`
+}
+
+/// Entries, click statistics and counters, stored as JSON documents in a `Store`.
+#[derive(Clone)]
+pub struct QuiViveCache {
+    store: Arc<Store>,
+    notifier: Notifier,
+}
+
+impl QuiViveCache {
+    pub fn new(store: Arc<Store>) -> Self {
+        QuiViveCache {
+            store: store,
+            notifier: Notifier::new(),
+        }
+    }
+
+    pub fn store(&self) -> &Store {
+        &*self.store
+    }
+
+    fn get_value<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StoreError> {
+        match self.store.get(key)? {
+            Some(value) => from_json(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn insert_with(&self, id: String, entry: QuiViveEntry, expiration: Option<usize>) -> Result<(), StoreError> {
+        let key = entry_key(&id);
+        self.store.put(&key, &to_json(&entry)?, to_ttl(expiration))?;
+        self.notifier.changed(&key);
+        Ok(())
+    }
+
+    /// Inserts an entry only if its id is not already taken, returning false otherwise.
+    pub fn insert_if_absent(&self, id: String, entry: QuiViveEntry, expiration: Option<usize>) -> Result<bool, StoreError> {
+        let key = entry_key(&id);
+        let inserted = self.store.put_if_absent(&key, &to_json(&entry)?, to_ttl(expiration))?;
+        if inserted {
+            self.notifier.changed(&key);
+        }
+        Ok(inserted)
+    }
+
+    pub fn get(&self, id: String) -> Result<Option<QuiViveEntry>, StoreError> {
+        self.get_value(&entry_key(&id))
+    }
+
+    pub fn remove(&self, id: String) -> Result<(), StoreError> {
+        let key = entry_key(&id);
+        if self.store.delete(&key)? {
+            self.notifier.changed(&key);
+        }
+        self.store.delete(&stats_key(&id))?;
+        Ok(())
+    }
+
+    /// Removes an entry and its statistics if the owner token allows it, checking the owner against the
+    /// removed value itself such that an entry re-created meanwhile by someone else is kept.
+    pub fn remove_owned(&self, id: String, owner_token: Option<&str>) -> Result<Result<(), Refused<()>>, StoreError> {
+        let key = entry_key(&id);
+
+        for _ in 0..MAX_SWAP_ATTEMPTS {
+            let current = match self.store.get(&key)? {
+                Some(value) => value,
+                None => return Ok(Err(Refused::Missing)),
+            };
+
+            let entry: QuiViveEntry = from_json(&current)?;
+            if !entry.is_owned_by(owner_token) {
+                return Ok(Err(Refused::NotOwner));
+            }
+
+            if self.store.compare_and_delete(&key, &current)? {
+                self.notifier.changed(&key);
+                self.store.delete(&stats_key(&id))?;
+                return Ok(Ok(()));
+            }
+        }
+
+        Err(StoreError::Other(format!("too many concurrent updates of {}", key)))
+    }
+
+    /// Notifies the listener right away, and then whenever the entry changes or the listener deadline passes.
+    /// The listener is given back if too many requests are waiting already.
+    pub fn listen(&self, id: &str, listener: Box<Listener>) -> Result<(), Box<Listener>> {
+        self.notifier.listen(&self.store, &entry_key(id), listener)
+    }
+
+    pub fn get_stats(&self, id: String) -> Result<Option<QuiViveStats>, StoreError> {
+        self.get_value(&stats_key(&id))
+    }
+
+    /// Changes the expiration of the click statistics of an entry, such that they expire along with it.
+    pub fn touch_stats(&self, id: &str, expiration: Option<usize>) -> Result<(), StoreError> {
+        self.store.touch(&stats_key(id), to_ttl(expiration)).map(|_| ())
+    }
+
+    /// Records a click on an entry, with stats expiring along with the entry.
+    pub fn record_click(&self, id: String, click: Click, expiration: Option<usize>) -> Result<(), StoreError> {
+        let key = stats_key(&id);
+
+        // compare-and-swap such that concurrent clicks are all counted
+        for _ in 0..MAX_SWAP_ATTEMPTS {
+            let current = self.store.get(&key)?;
+            let mut stats = match current {
+                Some(ref value) => from_json(value)?,
+                None => QuiViveStats::new(id.clone()),
+            };
+
+            stats.add_click(click.clone());
+
+            let expected = current.as_ref().map(String::as_str);
+            if self.store.compare_and_swap(&key, expected, &to_json(&stats)?, to_ttl(expiration))? {
+                return Ok(());
+            }
+        }
+
+        Err(StoreError::Other(format!("too many concurrent updates of {}", key)))
+    }
+
+    /// Increments a named counter that never expires, returning its new value (starting at 1).
+    pub fn next_sequence(&self, name: &str) -> Result<u64, StoreError> {
+        self.store.incr(&counter_key(name), 1).map(|x| x as u64)
+    }
+
+    /// Replaces an entry based on its current value (None if it does not exist), retrying if it changed meanwhile.
+    /// Returns the new entry, or the value given with `Update::Keep`.
+    pub fn update<T, F>(&self, id: String, mut f: F) -> Result<Result<QuiViveEntry, T>, StoreError>
+        where F: FnMut(Option<&QuiViveEntry>) -> Update<T>
+    {
+        let key = entry_key(&id);
+
+        for _ in 0..MAX_SWAP_ATTEMPTS {
+            let current = self.store.get(&key)?;
+            let existing: Option<QuiViveEntry> = match current {
+                Some(ref value) => Some(from_json(value)?),
+                None => None,
+            };
+
+            let (entry, expiration) = match f(existing.as_ref()) {
+                Update::Put(entry, expiration) => (entry, expiration),
+                Update::Keep(value) => return Ok(Err(value)),
+            };
+
+            let expected = current.as_ref().map(String::as_str);
+            if self.store.compare_and_swap(&key, expected, &to_json(&entry)?, to_ttl(expiration))? {
+                self.notifier.changed(&key);
+                return Ok(Ok(entry));
+            }
+        }
+
+        Err(StoreError::Other(format!("too many concurrent updates of {}", key)))
+    }
+
+    /// Replaces an existing entry if the owner token allows it, retrying if it changed meanwhile.
+    /// The owner is checked against the replaced value itself, such that reads and clicks recorded
+    /// meanwhile are kept, and an entry re-created meanwhile by someone else is never replaced.
+    pub fn update_owned<T, F>(&self, id: String, owner_token: Option<&str>, mut f: F)
+        -> Result<Result<QuiViveEntry, Refused<T>>, StoreError>
+        where F: FnMut(&QuiViveEntry) -> Update<T>
+    {
+        self.update(id, |existing| match existing {
+            None => Update::Keep(Refused::Missing),
+            Some(entry) if !entry.is_owned_by(owner_token) => Update::Keep(Refused::NotOwner),
+            Some(entry) => match f(entry) {
+                Update::Put(entry, expiration) => Update::Put(entry, expiration),
+                Update::Keep(value) => Update::Keep(Refused::Kept(value)),
+            },
+        })
+    }
+
+    /// Gets an entry for a read, removing it once its maximum read count is reached.
+    pub fn get_for_read(&self, id: String) -> Result<Option<QuiViveEntry>, StoreError> {
+        let key = entry_key(&id);
+
+        // compare-and-swap such that concurrent readers never observe the same read count
+        for _ in 0..MAX_SWAP_ATTEMPTS {
+            let current = match self.store.get(&key)? {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+
+            let mut entry: QuiViveEntry = from_json(&current)?;
+
+            if entry.max_reads == 0 {
+                return Ok(Some(entry));
+            }
+
+            if entry.reads >= entry.max_reads {
+                // another reader took the last read and is about to remove the entry
+                return Ok(None);
+            }
+
+            entry.reads += 1;
+
+            // an entry about to expire keeps at least one second, like with the redis store
+            let ttl = entry.ttl(get_timestamp()).map(|ttl| cmp::max(ttl, 1));
+            if self.store.compare_and_swap(&key, Some(current.as_str()), &to_json(&entry)?, ttl)? {
+                self.notifier.changed(&key);
+                if entry.reads >= entry.max_reads {
+                    self.remove(id)?;
+                }
+                return Ok(Some(entry));
+            }
+        }
+
+        Err(StoreError::Other(format!("too many concurrent reads of {}", key)))
+    }
+}
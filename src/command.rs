@@ -0,0 +1,139 @@
+use std::io;
+use std::io::{Read, Write};
+
+use clap::ArgMatches;
+use futures::Future;
+use tokio_core::reactor::Core;
+
+use crate::QuiViveConfig;
+use crate::admin::AdminCommand;
+
+use qui_vive_client::{ClientFuture, Expiration, InvitationOptions, KeyOptions, Link, QuiViveClient, UrlOptions};
+
+/// Client or admin subcommand, run instead of starting the server.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    Put { id: Option<String>, ttl: Option<Expiration>, content_type: Option<String> },
+    Get { id: String },
+    Shorten { url: String, ttl: Option<Expiration> },
+    Invite { dst_url: String, id_param: Option<String>, data: String, ttl: Option<Expiration> },
+    Remove { id: String },
+    Admin(AdminCommand),
+}
+
+fn parse_ttl(matches: &ArgMatches) -> Result<Option<Expiration>, String> {
+    match matches.value_of("ttl") {
+        Some(ttl) => match ttl.parse::<u32>() {
+            Ok(0) => Ok(Some(Expiration::Never)),
+            Ok(ttl) => Ok(Some(Expiration::After(ttl))),
+            Err(_) => Err(format!("invalid ttl: {}", ttl)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn value_of(matches: &ArgMatches, name: &str) -> Option<String> {
+    matches.value_of(name).map(String::from)
+}
+
+impl Command {
+    /// Gets the client subcommand from the command-line, with None when running the server.
+    pub fn from_matches(matches: &ArgMatches) -> Result<Option<Self>, String> {
+        let command = match matches.subcommand() {
+            ("put", Some(matches)) => Command::Put {
+                id: value_of(matches, "id"),
+                ttl: parse_ttl(matches)?,
+                content_type: value_of(matches, "content-type"),
+            },
+            ("get", Some(matches)) => Command::Get {
+                id: value_of(matches, "id").unwrap(),
+            },
+            ("shorten", Some(matches)) => Command::Shorten {
+                url: value_of(matches, "url").unwrap(),
+                ttl: parse_ttl(matches)?,
+            },
+            ("invite", Some(matches)) => Command::Invite {
+                dst_url: value_of(matches, "dst").unwrap(),
+                id_param: value_of(matches, "param"),
+                data: value_of(matches, "data").unwrap_or_default(),
+                ttl: parse_ttl(matches)?,
+            },
+            ("rm", Some(matches)) => Command::Remove {
+                id: value_of(matches, "id").unwrap(),
+            },
+            ("admin", Some(matches)) => Command::Admin(AdminCommand::from_matches(matches)?),
+            _ => return Ok(None),
+        };
+        Ok(Some(command))
+    }
+
+    pub fn run(&self, cfg: &QuiViveConfig) -> Result<(), String> {
+        match *self {
+            Command::Admin(ref admin) => admin.run(cfg),
+            _ => self.run_client(cfg.client_server_url(), cfg.client_api_key()),
+        }
+    }
+
+    /// Sends the client subcommand to the server, writing its result to standard output.
+    fn run_client(&self, server_url: &str, api_key: Option<&str>) -> Result<(), String> {
+        let mut core = Core::new().map_err(|err| err.to_string())?;
+        let mut client = QuiViveClient::new(server_url, &core.handle()).map_err(|err| err.to_string())?;
+
+        if let Some(api_key) = api_key {
+            client = client.with_api_key(api_key);
+        }
+
+        let output = match *self {
+            Command::Put { ref id, ttl, ref content_type } => {
+                let mut value = Vec::new();
+                io::stdin().read_to_end(&mut value).map_err(|err| format!("failed to read standard input: {}", err))?;
+
+                let mut options = KeyOptions::new();
+                if let Some(ttl) = ttl {
+                    options = options.with_expiration(ttl);
+                }
+                if let Some(ref content_type) = *content_type {
+                    options = options.with_content_type(content_type);
+                }
+
+                let link = match *id {
+                    Some(ref id) => client.set_key(id, value, options),
+                    None => client.create_key(value, options),
+                };
+                core.run(link_output(link))
+            }
+            Command::Get { ref id } => {
+                core.run(client.get_key(id))
+            }
+            Command::Shorten { ref url, ttl } => {
+                let mut options = UrlOptions::new();
+                if let Some(ttl) = ttl {
+                    options = options.with_expiration(ttl);
+                }
+                core.run(link_output(client.shorten_url(url, options)))
+            }
+            Command::Invite { ref dst_url, ref id_param, ref data, ttl } => {
+                let mut options = InvitationOptions::new(dst_url);
+                if let Some(ref id_param) = *id_param {
+                    options = options.with_id_param(id_param);
+                }
+                if let Some(ttl) = ttl {
+                    options = options.with_expiration(ttl);
+                }
+                core.run(link_output(client.create_invitation(data.clone().into_bytes(), options)))
+            }
+            Command::Remove { ref id } => {
+                core.run(client.delete_key(id).map(|_| Vec::new()))
+            }
+            Command::Admin(_) => unreachable!(),
+        };
+
+        let output = output.map_err(|err| err.to_string())?;
+        let mut stdout = io::stdout();
+        stdout.write_all(&output).and_then(|_| stdout.flush()).map_err(|err| err.to_string())
+    }
+}
+
+fn link_output(link: ClientFuture<Link>) -> ClientFuture<Vec<u8>> {
+    Box::new(link.map(|link| format!("{}\n", link.url).into_bytes()))
+}
@@ -1,29 +1,245 @@
 
-use clap::App;
+use clap::ArgMatches;
+use regex::Regex;
 use std::env;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::str::FromStr;
+use toml;
+use url::Url;
 
-#[derive(Clone,PartialEq)]
+use crate::auth;
+use crate::auth::{ApiKey, REDACTED};
+use crate::idgen;
+use crate::idgen::IdGenerator;
+use uuid::Uuid;
+
+#[derive(Clone)]
 pub enum CustomIdFormat {
     None,
     Uuid,
+    Ulid,
+    Regex(Regex),
     All,
 }
 
+impl PartialEq for CustomIdFormat {
+    fn eq(&self, other: &CustomIdFormat) -> bool {
+        match (self, other) {
+            (&CustomIdFormat::None, &CustomIdFormat::None) => true,
+            (&CustomIdFormat::Uuid, &CustomIdFormat::Uuid) => true,
+            (&CustomIdFormat::Ulid, &CustomIdFormat::Ulid) => true,
+            (&CustomIdFormat::Regex(ref a), &CustomIdFormat::Regex(ref b)) => a.as_str() == b.as_str(),
+            (&CustomIdFormat::All, &CustomIdFormat::All) => true,
+            _ => false,
+        }
+    }
+}
+
+impl CustomIdFormat {
+    /// Builds a regex format, anchored such that the whole id must match the pattern.
+    pub fn regex(pattern: &str) -> Result<Self, String> {
+        Regex::new(&format!("^(?:{})$", pattern))
+            .map(CustomIdFormat::Regex)
+            .map_err(|err| format!("invalid custom id regex: {}", err))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match *self {
+            CustomIdFormat::None => "none",
+            CustomIdFormat::Uuid => "uuid",
+            CustomIdFormat::Ulid => "ulid",
+            CustomIdFormat::Regex(_) => "regex",
+            CustomIdFormat::All => "all",
+        }
+    }
+
+    /// Gets the pattern of a regex format, without the anchors added by `CustomIdFormat::regex`.
+    pub fn pattern(&self) -> Option<&str> {
+        match *self {
+            CustomIdFormat::Regex(ref regex) => {
+                let pattern = regex.as_str();
+                Some(&pattern[4..pattern.len() - 2])
+            }
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, id: &str) -> bool {
+        match *self {
+            CustomIdFormat::None => false,
+            CustomIdFormat::Uuid => Uuid::parse_str(id).is_ok(),
+            CustomIdFormat::Ulid => idgen::is_ulid(id),
+            CustomIdFormat::Regex(ref regex) => regex.is_match(id),
+            CustomIdFormat::All => true,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct QuiViveConfig {
     pub external_url: String,
     pub listener_url: String,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub tls_redirect_url: Option<String>,
     pub redis_hostname: Option<String>,
     pub redis_password: Option<String>,
     pub cache_type: Option<String>,
+    pub data_dir: Option<String>,
     pub id_length: u32,
     pub id_charset: String,
+    pub id_generator: IdGenerator,
+    pub allowed_id_generators: Vec<IdGenerator>,
     pub custom_id_format: CustomIdFormat,
+    pub custom_id_min_length: Option<usize>,
+    pub custom_id_max_length: Option<usize>,
+    pub custom_id_prefix: Option<String>,
     pub default_expiration: Option<u32>,
     pub max_value_size: usize,
+    pub max_wait: u32,
+    pub redirect_status: u16,
+    pub inv_redirect_status: u16,
+    pub allowed_schemes: Vec<String>,
+    pub allowed_domains: Vec<String>,
+    pub denied_domains: Vec<String>,
+    pub api_keys: Vec<ApiKey>,
+    pub api_key_file: Option<String>,
+    pub server_url: Option<String>,
+    pub client_api_key: Option<String>,
+    pub print_config: bool,
+    invalid_options: Vec<String>, // environment and command-line values that could not be parsed, reported by validate()
+}
+
+/// Configuration file layout, using the same option names as the command-line interface.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    external_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    listener_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_redirect_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redis_hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redis_password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_length: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_charset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_generator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_id_generators: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_id_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_id_regex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_id_min_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_id_max_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_id_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default_expiration: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_value_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_wait: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inv_redirect_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_schemes: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_domains: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    denied_domains: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_keys: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_api_key: Option<String>,
+}
+
+const CACHE_TYPES: [&str; 3] = ["redis", "memory", "file"];
+
+const MAX_EXPIRATION: u32 = 10 * 365 * 86400; // 10 years
+
+const ID_LENGTH: u32 = 9;
+
+pub const REDIRECT_STATUSES: [u16; 5] = [301, 302, 303, 307, 308];
+
+pub fn parse_redirect_status(value: &str) -> Option<u16> {
+    value.trim().parse::<u16>().ok().filter(|status| REDIRECT_STATUSES.contains(status))
+}
+
+fn parse_custom_id_format(value: &str) -> Result<CustomIdFormat, String> {
+    match value.trim().to_lowercase().as_str() {
+        "none" => Ok(CustomIdFormat::None),
+        "uuid" => Ok(CustomIdFormat::Uuid),
+        "ulid" => Ok(CustomIdFormat::Ulid),
+        "all" => Ok(CustomIdFormat::All),
+        _ => Err(format!("invalid custom id format {}: expected one of none, uuid, ulid, all", value)),
+    }
+}
+
+fn parse_id_generators(value: &str) -> Result<Vec<IdGenerator>, String> {
+    parse_list(value).iter().map(|x| x.parse::<IdGenerator>()).collect()
+}
+
+fn check_not_redacted(name: &str, value: &Option<String>) -> Result<(), String> {
+    match *value {
+        Some(ref value) if value == REDACTED => Err(format!("{} is redacted, set the actual secret", name)),
+        _ => Ok(()),
+    }
+}
+
+fn parse_api_keys<'a, I: Iterator<Item=&'a str>>(values: I) -> Result<Vec<ApiKey>, String> {
+    values.map(|api_key| ApiKey::parse(api_key).map_err(|err| format!("invalid api key: {}", err)))
+        .collect()
+}
+
+/// Resolves the socket address to listen on from a listener URL like http://0.0.0.0:8080.
+pub fn parse_listener_url(value: &str) -> Result<SocketAddr, String> {
+    let url = Url::parse(value).map_err(|err| format!("invalid listener url {}: {}", value, err))?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("invalid listener url {}: the scheme must be http or https", value));
+    }
+
+    let host = url.host_str().ok_or_else(|| format!("invalid listener url {}: missing host", value))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    format!("{}:{}", host, port).to_socket_addrs()
+        .map_err(|err| format!("invalid listener url {}: {}", value, err))?
+        .next()
+        .ok_or_else(|| format!("invalid listener url {}: the host does not resolve", value))
 }
 
-const ID_LENGTH: u32 = 12;
+fn parse_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|x| x.trim().to_lowercase())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
 
 const ID_CHARSET: &str = "23456789\
             abcdefghjkimnpqrstuvwxyz\
@@ -33,58 +249,344 @@ impl QuiViveConfig {
 
     pub fn new() -> Self {
         QuiViveConfig {
-            external_url: "".to_string(),
-            listener_url: "".to_string(),
+            external_url: "http://127.0.0.1:8080".to_string(),
+            listener_url: "http://0.0.0.0:8080".to_string(),
+            tls_cert: None,
+            tls_key: None,
+            tls_redirect_url: None,
             redis_hostname: None,
             redis_password: None,
             cache_type: None,
+            data_dir: None,
             id_length: ID_LENGTH,
             id_charset: ID_CHARSET.to_string(),
+            id_generator: IdGenerator::Random,
+            // sequential and words ids are easy to guess, so clients cannot select them unless allowed
+            allowed_id_generators: vec![IdGenerator::Random, IdGenerator::Uuid, IdGenerator::Ulid, IdGenerator::Nanoid],
             custom_id_format: CustomIdFormat::All,
+            custom_id_min_length: None,
+            custom_id_max_length: None,
+            custom_id_prefix: None,
             default_expiration: Some(86400), // 24 hours
-            max_value_size: 1024*1024 // 1MB
+            max_value_size: 1024*1024, // 1MB
+            max_wait: 60, // 1 minute
+            redirect_status: 301, // moved permanently
+            inv_redirect_status: 302, // found, such that browsers do not cache invitations
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            allowed_domains: Vec::new(),
+            denied_domains: Vec::new(),
+            api_keys: Vec::new(),
+            api_key_file: None,
+            server_url: None,
+            client_api_key: None,
+            print_config: false,
+            invalid_options: Vec::new(),
         }
     }
 
-    pub fn load_cli(&mut self) {
-        let yaml = load_yaml!("cli.yml");
-        let app = App::from_yaml(yaml);
-        let matches = app.version(crate_version!()).get_matches();
+    pub fn with_external_url(mut self, external_url: &str) -> Self {
+        self.external_url = external_url.to_string();
+        self
+    }
 
-        self.listener_url = matches.value_of("listener-url").unwrap_or("http://0.0.0.0:8080").to_string();
-        self.external_url = matches.value_of("external-url").unwrap_or("http://127.0.0.1:8080").to_string();
+    pub fn with_listener_url(mut self, listener_url: &str) -> Self {
+        self.listener_url = listener_url.to_string();
+        self
+    }
 
-        let redis_hostname = if let Some(value) = matches.value_of("redis-hostname") {
-            Some(String::from(value))
-        } else {
-            None
-        };
+    pub fn with_memory_cache(mut self) -> Self {
+        self.cache_type = Some("memory".to_string());
+        self
+    }
 
-        let redis_password = if let Some(value) = matches.value_of("redis-password") {
-            Some(String::from(value))
-        } else {
-            None
-        };
+    pub fn with_file_cache(mut self, data_dir: &str) -> Self {
+        self.cache_type = Some("file".to_string());
+        self.data_dir = Some(data_dir.to_string());
+        self
+    }
 
-        self.redis_hostname = redis_hostname;
-        self.redis_password = redis_password;
+    pub fn with_redis_cache(mut self, redis_hostname: &str, redis_password: Option<&str>) -> Self {
+        self.cache_type = Some("redis".to_string());
+        self.redis_hostname = Some(redis_hostname.to_string());
+        self.redis_password = redis_password.map(String::from);
+        self
+    }
 
-        let cache_type = if let Some(value) = matches.value_of("cache-type") {
-            Some(String::from(value))
-        } else {
-            None
-        };
+    pub fn with_id_generator(mut self, id_generator: IdGenerator) -> Self {
+        self.id_generator = id_generator;
+        self
+    }
+
+    pub fn with_allowed_id_generators(mut self, allowed_id_generators: &[IdGenerator]) -> Self {
+        self.allowed_id_generators = allowed_id_generators.to_vec();
+        self
+    }
+
+    pub fn with_custom_id_format(mut self, custom_id_format: CustomIdFormat) -> Self {
+        self.custom_id_format = custom_id_format;
+        self
+    }
+
+    /// Sets the default expiration in seconds, with None for no expiration.
+    pub fn with_default_expiration(mut self, default_expiration: Option<u32>) -> Self {
+        self.default_expiration = default_expiration;
+        self
+    }
+
+    pub fn with_max_value_size(mut self, max_value_size: usize) -> Self {
+        self.max_value_size = max_value_size;
+        self
+    }
+
+    pub fn with_allowed_domains(mut self, allowed_domains: &[&str]) -> Self {
+        self.allowed_domains = parse_list(&allowed_domains.join(","));
+        self
+    }
+
+    pub fn with_api_key(mut self, api_key: ApiKey) -> Self {
+        self.api_keys.push(api_key);
+        self
+    }
+
+    /// Loads the configuration with the following precedence: defaults < file < environment < command-line.
+    pub fn load(&mut self, matches: &ArgMatches) -> Result<(), String> {
+        let config_file = matches.value_of("config").map(String::from)
+            .or_else(|| env::var("CONFIG_FILE").ok());
+
+        if let Some(config_file) = config_file {
+            self.load_file(&config_file)?;
+        }
+
+        self.load_env();
+        self.load_cli(matches);
+        Ok(())
+    }
+
+    pub fn load_file(&mut self, path: &str) -> Result<(), String> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read config file {}: {}", path, err))?;
+        self.load_toml(&data)
+            .map_err(|err| format!("invalid config file {}: {}", path, err))
+    }
+
+    /// Loads the options of a configuration file, which rejects the secrets redacted by `to_toml`.
+    pub fn load_toml(&mut self, data: &str) -> Result<(), String> {
+        let file: ConfigFile = toml::from_str(data).map_err(|err| err.to_string())?;
+
+        check_not_redacted("redis-password", &file.redis_password)?;
+        check_not_redacted("client-api-key", &file.client_api_key)?;
+
+        if let Some(external_url) = file.external_url {
+            self.external_url = external_url;
+        }
+
+        if let Some(listener_url) = file.listener_url {
+            self.listener_url = listener_url;
+        }
+
+        if file.tls_cert.is_some() {
+            self.tls_cert = file.tls_cert;
+        }
 
-        self.cache_type = cache_type;
+        if file.tls_key.is_some() {
+            self.tls_key = file.tls_key;
+        }
+
+        if file.tls_redirect_url.is_some() {
+            self.tls_redirect_url = file.tls_redirect_url;
+        }
+
+        if file.redis_hostname.is_some() {
+            self.redis_hostname = file.redis_hostname;
+        }
+
+        if file.redis_password.is_some() {
+            self.redis_password = file.redis_password;
+        }
+
+        if file.cache_type.is_some() {
+            self.cache_type = file.cache_type;
+        }
+
+        if file.data_dir.is_some() {
+            self.data_dir = file.data_dir;
+        }
+
+        if let Some(id_length) = file.id_length {
+            self.id_length = id_length;
+        }
+
+        if let Some(id_charset) = file.id_charset {
+            self.id_charset = id_charset;
+        }
+
+        if let Some(id_generator) = file.id_generator {
+            self.id_generator = id_generator.parse::<IdGenerator>()?;
+        }
+
+        if let Some(allowed_id_generators) = file.allowed_id_generators {
+            self.allowed_id_generators = parse_id_generators(&allowed_id_generators.join(","))?;
+        }
+
+        if let Some(custom_id_format) = file.custom_id_format {
+            self.custom_id_format = parse_custom_id_format(&custom_id_format)?;
+        }
+
+        if let Some(custom_id_regex) = file.custom_id_regex {
+            self.custom_id_format = CustomIdFormat::regex(&custom_id_regex)?;
+        }
+
+        if file.custom_id_min_length.is_some() {
+            self.custom_id_min_length = file.custom_id_min_length;
+        }
+
+        if file.custom_id_max_length.is_some() {
+            self.custom_id_max_length = file.custom_id_max_length;
+        }
+
+        if file.custom_id_prefix.is_some() {
+            self.custom_id_prefix = file.custom_id_prefix;
+        }
+
+        if let Some(default_expiration) = file.default_expiration {
+            self.default_expiration = if default_expiration == 0 {
+                None
+            } else {
+                Some(default_expiration)
+            };
+        }
+
+        if let Some(max_value_size) = file.max_value_size {
+            self.max_value_size = max_value_size;
+        }
+
+        if let Some(max_wait) = file.max_wait {
+            self.max_wait = max_wait;
+        }
+
+        if let Some(redirect_status) = file.redirect_status {
+            self.redirect_status = parse_redirect_status(&redirect_status.to_string())
+                .ok_or_else(|| format!("invalid redirect status: {}", redirect_status))?;
+        }
+
+        if let Some(inv_redirect_status) = file.inv_redirect_status {
+            self.inv_redirect_status = parse_redirect_status(&inv_redirect_status.to_string())
+                .ok_or_else(|| format!("invalid redirect status: {}", inv_redirect_status))?;
+        }
+
+        if let Some(allowed_schemes) = file.allowed_schemes {
+            self.allowed_schemes = parse_list(&allowed_schemes.join(","));
+        }
+
+        if let Some(allowed_domains) = file.allowed_domains {
+            self.allowed_domains = parse_list(&allowed_domains.join(","));
+        }
+
+        if let Some(denied_domains) = file.denied_domains {
+            self.denied_domains = parse_list(&denied_domains.join(","));
+        }
+
+        if let Some(api_keys) = file.api_keys {
+            self.api_keys = parse_api_keys(api_keys.iter().map(String::as_str))?;
+        }
+
+        if file.api_key_file.is_some() {
+            self.api_key_file = file.api_key_file;
+        }
+
+        if file.server_url.is_some() {
+            self.server_url = file.server_url;
+        }
+
+        if file.client_api_key.is_some() {
+            self.client_api_key = file.client_api_key;
+        }
+
+        Ok(())
+    }
+
+    // parses a number from the environment or the command-line, recording it for validate() if it is invalid
+    fn parse_number<T: FromStr>(&mut self, name: &str, value: &str) -> Option<T> {
+        match value.trim().parse::<T>() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.invalid_options.push(format!("invalid {}: {}", name, value));
+                None
+            }
+        }
+    }
+
+    fn parse_redirect_option(&mut self, value: &str) -> Option<u16> {
+        let redirect_status = parse_redirect_status(value);
+        if redirect_status.is_none() {
+            self.invalid_options.push(format!("invalid redirect status: {}", value));
+        }
+        redirect_status
+    }
+
+    fn load_cli(&mut self, matches: &ArgMatches) {
+        if let Some(listener_url) = matches.value_of("listener-url") {
+            self.listener_url = listener_url.to_string();
+        }
+
+        if let Some(external_url) = matches.value_of("external-url") {
+            self.external_url = external_url.to_string();
+        }
+
+        if let Some(tls_cert) = matches.value_of("tls-cert") {
+            self.tls_cert = Some(tls_cert.to_string());
+        }
+
+        if let Some(tls_key) = matches.value_of("tls-key") {
+            self.tls_key = Some(tls_key.to_string());
+        }
 
-        self.id_length = value_t!(matches, "id-length", u32).unwrap_or(9);
+        if let Some(tls_redirect_url) = matches.value_of("tls-redirect-url") {
+            self.tls_redirect_url = Some(tls_redirect_url.to_string());
+        }
+
+        if let Some(value) = matches.value_of("redis-hostname") {
+            self.redis_hostname = Some(String::from(value));
+        }
+
+        if let Some(value) = matches.value_of("redis-password") {
+            self.redis_password = Some(String::from(value));
+        }
+
+        if let Some(value) = matches.value_of("cache-type") {
+            self.cache_type = Some(String::from(value));
+        }
+
+        if let Some(data_dir) = matches.value_of("data-dir") {
+            self.data_dir = Some(data_dir.to_string());
+        }
+
+        if let Some(id_length) = matches.value_of("id-length") {
+            if let Some(id_length) = self.parse_number::<u32>("id length", id_length) {
+                self.id_length = id_length;
+            }
+        }
 
         if let Some(id_charset) = matches.value_of("id-charset") {
             self.id_charset = id_charset.to_string();
         }
 
+        if let Some(id_generator) = matches.value_of("id-generator") {
+            match id_generator.parse::<IdGenerator>() {
+                Ok(id_generator) => self.id_generator = id_generator,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
+        if let Some(allowed_id_generators) = matches.value_of("allowed-id-generators") {
+            match parse_id_generators(allowed_id_generators) {
+                Ok(allowed_id_generators) => self.allowed_id_generators = allowed_id_generators,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
         if let Some(default_expiration) = matches.value_of("default-expiration") {
-            if let Ok(default_expiration) = default_expiration.parse::<u32>() {
+            if let Some(default_expiration) = self.parse_number::<u32>("default expiration", default_expiration) {
                 self.default_expiration = if default_expiration == 0 {
                     None
                 } else {
@@ -94,19 +596,92 @@ impl QuiViveConfig {
         }
 
         if let Some(custom_id_format) = matches.value_of("custom-id-format") {
-            self.custom_id_format = match custom_id_format.to_lowercase().as_str() {
-                "none" => CustomIdFormat::None,
-                "uuid" => CustomIdFormat::Uuid,
-                "all" => CustomIdFormat::All,
-                _ => CustomIdFormat::All,
+            match parse_custom_id_format(custom_id_format) {
+                Ok(custom_id_format) => self.custom_id_format = custom_id_format,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
+        if let Some(custom_id_regex) = matches.value_of("custom-id-regex") {
+            match CustomIdFormat::regex(custom_id_regex) {
+                Ok(custom_id_format) => self.custom_id_format = custom_id_format,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
+        if let Some(custom_id_min_length) = matches.value_of("custom-id-min-length") {
+            if let Some(custom_id_min_length) = self.parse_number::<usize>("custom id min length", custom_id_min_length) {
+                self.custom_id_min_length = Some(custom_id_min_length);
             }
         }
 
+        if let Some(custom_id_max_length) = matches.value_of("custom-id-max-length") {
+            if let Some(custom_id_max_length) = self.parse_number::<usize>("custom id max length", custom_id_max_length) {
+                self.custom_id_max_length = Some(custom_id_max_length);
+            }
+        }
+
+        if let Some(custom_id_prefix) = matches.value_of("custom-id-prefix") {
+            self.custom_id_prefix = Some(custom_id_prefix.to_string());
+        }
+
         if let Some(max_value_size) = matches.value_of("max-value-size") {
-            if let Ok(max_value_size) = max_value_size.parse::<usize>() {
+            if let Some(max_value_size) = self.parse_number::<usize>("max value size", max_value_size) {
                 self.max_value_size = max_value_size;
             }
         }
+
+        if let Some(max_wait) = matches.value_of("max-wait") {
+            if let Some(max_wait) = self.parse_number::<u32>("max wait", max_wait) {
+                self.max_wait = max_wait;
+            }
+        }
+
+        if let Some(redirect_status) = matches.value_of("redirect-status") {
+            if let Some(redirect_status) = self.parse_redirect_option(redirect_status) {
+                self.redirect_status = redirect_status;
+            }
+        }
+
+        if let Some(inv_redirect_status) = matches.value_of("inv-redirect-status") {
+            if let Some(inv_redirect_status) = self.parse_redirect_option(inv_redirect_status) {
+                self.inv_redirect_status = inv_redirect_status;
+            }
+        }
+
+        if let Some(allowed_schemes) = matches.value_of("allowed-schemes") {
+            self.allowed_schemes = parse_list(allowed_schemes);
+        }
+
+        if let Some(allowed_domains) = matches.value_of("allowed-domains") {
+            self.allowed_domains = parse_list(allowed_domains);
+        }
+
+        if let Some(denied_domains) = matches.value_of("denied-domains") {
+            self.denied_domains = parse_list(denied_domains);
+        }
+
+        if let Some(api_keys) = matches.values_of("api-key") {
+            // a single malformed key must not leave the list empty, which disables authentication
+            match parse_api_keys(api_keys) {
+                Ok(api_keys) => self.api_keys = api_keys,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
+        if let Some(api_key_file) = matches.value_of("api-key-file") {
+            self.api_key_file = Some(api_key_file.to_string());
+        }
+
+        if let Some(server_url) = matches.value_of("server-url") {
+            self.server_url = Some(server_url.to_string());
+        }
+
+        if let Some(client_api_key) = matches.value_of("client-api-key") {
+            self.client_api_key = Some(client_api_key.to_string());
+        }
+
+        self.print_config = matches.is_present("print-config");
     }
 
     pub fn load_env(&mut self) {
@@ -118,6 +693,18 @@ impl QuiViveConfig {
             self.listener_url = Some(val).unwrap();
         }
 
+        if let Ok(val) = env::var("TLS_CERT") {
+            self.tls_cert = Some(val);
+        }
+
+        if let Ok(val) = env::var("TLS_KEY") {
+            self.tls_key = Some(val);
+        }
+
+        if let Ok(val) = env::var("TLS_REDIRECT_URL") {
+            self.tls_redirect_url = Some(val);
+        }
+
         if let Ok(val) = env::var("REDIS_HOSTNAME") {
             self.redis_hostname = Some(val);
         }
@@ -130,8 +717,12 @@ impl QuiViveConfig {
             self.cache_type = Some(val);
         }
 
+        if let Ok(val) = env::var("DATA_DIR") {
+            self.data_dir = Some(val);
+        }
+
         if let Ok(val) = env::var("ID_LENGTH") {
-            if let Ok(id_length) = Some(val).unwrap().parse::<u32>() {
+            if let Some(id_length) = self.parse_number::<u32>("id length", &val) {
                 self.id_length = id_length;
             }
         }
@@ -140,8 +731,22 @@ impl QuiViveConfig {
             self.id_charset = Some(val).unwrap();
         }
 
+        if let Ok(val) = env::var("ID_GENERATOR") {
+            match val.parse::<IdGenerator>() {
+                Ok(id_generator) => self.id_generator = id_generator,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
+        if let Ok(val) = env::var("ALLOWED_ID_GENERATORS") {
+            match parse_id_generators(&val) {
+                Ok(allowed_id_generators) => self.allowed_id_generators = allowed_id_generators,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
         if let Ok(val) = env::var("DEFAULT_EXPIRATION") {
-            if let Ok(default_expiration) = Some(val).unwrap().parse::<u32>() {
+            if let Some(default_expiration) = self.parse_number::<u32>("default expiration", &val) {
                 self.default_expiration = if default_expiration == 0 {
                     None
                 } else {
@@ -151,18 +756,288 @@ impl QuiViveConfig {
         }
 
         if let Ok(val) = env::var("CUSTOM_ID_FORMAT") {
-            self.custom_id_format = match val.to_lowercase().as_str() {
-                "none" => CustomIdFormat::None,
-                "uuid" => CustomIdFormat::Uuid,
-                "all" => CustomIdFormat::All,
-                _ => CustomIdFormat::All,
+            match parse_custom_id_format(&val) {
+                Ok(custom_id_format) => self.custom_id_format = custom_id_format,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
+        if let Ok(val) = env::var("CUSTOM_ID_REGEX") {
+            match CustomIdFormat::regex(&val) {
+                Ok(custom_id_format) => self.custom_id_format = custom_id_format,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
+        if let Ok(val) = env::var("CUSTOM_ID_MIN_LENGTH") {
+            if let Some(custom_id_min_length) = self.parse_number::<usize>("custom id min length", &val) {
+                self.custom_id_min_length = Some(custom_id_min_length);
             }
         }
 
+        if let Ok(val) = env::var("CUSTOM_ID_MAX_LENGTH") {
+            if let Some(custom_id_max_length) = self.parse_number::<usize>("custom id max length", &val) {
+                self.custom_id_max_length = Some(custom_id_max_length);
+            }
+        }
+
+        if let Ok(val) = env::var("CUSTOM_ID_PREFIX") {
+            self.custom_id_prefix = Some(val);
+        }
+
         if let Ok(val) = env::var("MAX_VALUE_SIZE") {
-            if let Ok(max_value_size) = Some(val).unwrap().parse::<usize>() {
+            if let Some(max_value_size) = self.parse_number::<usize>("max value size", &val) {
                 self.max_value_size = max_value_size;
             }
         }
+
+        if let Ok(val) = env::var("MAX_WAIT") {
+            if let Some(max_wait) = self.parse_number::<u32>("max wait", &val) {
+                self.max_wait = max_wait;
+            }
+        }
+
+        if let Ok(val) = env::var("REDIRECT_STATUS") {
+            if let Some(redirect_status) = self.parse_redirect_option(&val) {
+                self.redirect_status = redirect_status;
+            }
+        }
+
+        if let Ok(val) = env::var("INV_REDIRECT_STATUS") {
+            if let Some(inv_redirect_status) = self.parse_redirect_option(&val) {
+                self.inv_redirect_status = inv_redirect_status;
+            }
+        }
+
+        if let Ok(val) = env::var("ALLOWED_SCHEMES") {
+            self.allowed_schemes = parse_list(&val);
+        }
+
+        if let Ok(val) = env::var("ALLOWED_DOMAINS") {
+            self.allowed_domains = parse_list(&val);
+        }
+
+        if let Ok(val) = env::var("DENIED_DOMAINS") {
+            self.denied_domains = parse_list(&val);
+        }
+
+        if let Ok(val) = env::var("API_KEYS") {
+            match parse_api_keys(val.split_whitespace()) {
+                Ok(api_keys) => self.api_keys = api_keys,
+                Err(err) => self.invalid_options.push(err),
+            }
+        }
+
+        if let Ok(val) = env::var("API_KEY_FILE") {
+            self.api_key_file = Some(val);
+        }
+
+        if let Ok(val) = env::var("QUI_VIVE_URL") {
+            self.server_url = Some(val);
+        }
+
+        if let Ok(val) = env::var("QUI_VIVE_API_KEY") {
+            self.client_api_key = Some(val);
+        }
+    }
+
+    /// Checks the configuration, returning every problem found instead of stopping at the first one.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = self.invalid_options.clone();
+
+        match Url::parse(&self.external_url) {
+            Ok(ref url) if url.scheme() == "http" || url.scheme() == "https" => {}
+            Ok(_) => errors.push(format!("invalid external url {}: the scheme must be http or https", self.external_url)),
+            Err(err) => errors.push(format!("invalid external url {}: {}", self.external_url, err)),
+        }
+
+        if let Err(err) = parse_listener_url(&self.listener_url) {
+            errors.push(err);
+        }
+
+        if self.listener_url.starts_with("https:") {
+            if self.tls_cert.is_none() {
+                errors.push("an https listener url requires a tls certificate (--tls-cert)".to_string());
+            }
+            if self.tls_key.is_none() {
+                errors.push("an https listener url requires a tls private key (--tls-key)".to_string());
+            }
+        }
+
+        if let Some(ref tls_redirect_url) = self.tls_redirect_url {
+            if let Err(err) = parse_listener_url(tls_redirect_url) {
+                errors.push(err);
+            }
+        }
+
+        let cache_type = self.cache_type.as_ref().map_or("memory", |x| x.as_str());
+        if !CACHE_TYPES.contains(&cache_type) {
+            errors.push(format!("invalid cache type {}: expected one of {}", cache_type, CACHE_TYPES.join(", ")));
+        }
+
+        if cache_type == "file" {
+            if let Some(ref data_dir) = self.data_dir {
+                if Path::new(data_dir).is_file() {
+                    errors.push(format!("invalid data directory {}: it is a file", data_dir));
+                }
+            }
+        }
+
+        if self.id_length == 0 {
+            errors.push("the id length must be greater than 0".to_string());
+        }
+
+        if self.id_charset.is_empty() {
+            errors.push("the id charset must not be empty".to_string());
+        } else if !self.id_charset.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            errors.push("the id charset must only contain ASCII letters, digits, dashes and underscores".to_string());
+        }
+
+        if let (Some(min_length), Some(max_length)) = (self.custom_id_min_length, self.custom_id_max_length) {
+            if min_length > max_length {
+                errors.push(format!("the minimum custom id length ({}) exceeds the maximum ({})", min_length, max_length));
+            }
+        }
+
+        if let Some(default_expiration) = self.default_expiration {
+            if default_expiration > MAX_EXPIRATION {
+                errors.push(format!("the default expiration ({} seconds) exceeds 10 years, use 0 for no expiration", default_expiration));
+            }
+        }
+
+        if self.max_value_size == 0 {
+            errors.push("the maximum value size must be greater than 0".to_string());
+        }
+
+        if self.allowed_schemes.is_empty() {
+            errors.push("at least one destination url scheme must be allowed".to_string());
+        }
+
+        errors
+    }
+
+    /// Dumps the effective configuration in the configuration file format, with secrets redacted.
+    pub fn to_toml(&self) -> String {
+        let api_keys = self.api_keys.iter().map(|api_key| {
+            let scopes: Vec<&str> = api_key.scopes.iter().map(|scope| scope.as_str()).collect();
+            format!("{}:{}", REDACTED, scopes.join(","))
+        }).collect();
+
+        let file = ConfigFile {
+            external_url: Some(self.external_url.clone()),
+            listener_url: Some(self.listener_url.clone()),
+            tls_cert: self.tls_cert.clone(),
+            tls_key: self.tls_key.clone(),
+            tls_redirect_url: self.tls_redirect_url.clone(),
+            redis_hostname: self.redis_hostname.clone(),
+            redis_password: self.redis_password.as_ref().map(|_| REDACTED.to_string()),
+            cache_type: self.cache_type.clone(),
+            data_dir: self.data_dir.clone(),
+            id_length: Some(self.id_length),
+            id_charset: Some(self.id_charset.clone()),
+            id_generator: Some(self.id_generator.as_str().to_string()),
+            allowed_id_generators: Some(self.allowed_id_generators.iter().map(|x| x.as_str().to_string()).collect()),
+            custom_id_format: match self.custom_id_format {
+                CustomIdFormat::Regex(_) => None,
+                ref custom_id_format => Some(custom_id_format.as_str().to_string()),
+            },
+            custom_id_regex: self.custom_id_format.pattern().map(String::from),
+            custom_id_min_length: self.custom_id_min_length,
+            custom_id_max_length: self.custom_id_max_length,
+            custom_id_prefix: self.custom_id_prefix.clone(),
+            default_expiration: Some(self.default_expiration.unwrap_or(0)),
+            max_value_size: Some(self.max_value_size),
+            max_wait: Some(self.max_wait),
+            redirect_status: Some(self.redirect_status),
+            inv_redirect_status: Some(self.inv_redirect_status),
+            allowed_schemes: Some(self.allowed_schemes.clone()),
+            allowed_domains: Some(self.allowed_domains.clone()),
+            denied_domains: Some(self.denied_domains.clone()),
+            api_keys: Some(api_keys),
+            api_key_file: self.api_key_file.clone(),
+            server_url: self.server_url.clone(),
+            client_api_key: self.client_api_key.as_ref().map(|_| REDACTED.to_string()),
+        };
+
+        toml::to_string(&file).unwrap_or_default()
+    }
+
+    /// Returns true if clients can select the id generator, which is always the case for the default one.
+    pub fn is_allowed_id_generator(&self, id_generator: IdGenerator) -> bool {
+        id_generator == self.id_generator || self.allowed_id_generators.contains(&id_generator)
+    }
+
+    /// Checks a custom id against the custom id format, length limits and prefix.
+    pub fn is_valid_custom_id(&self, id: &str) -> bool {
+        if self.custom_id_min_length.map_or(false, |min_length| id.len() < min_length) {
+            return false;
+        }
+
+        if self.custom_id_max_length.map_or(false, |max_length| id.len() > max_length) {
+            return false;
+        }
+
+        if let Some(ref prefix) = self.custom_id_prefix {
+            if !id.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+
+        self.custom_id_format.matches(id)
+    }
+
+    /// URL of the server used by client subcommands, which is the external URL unless set.
+    pub fn client_server_url(&self) -> &str {
+        self.server_url.as_ref().unwrap_or(&self.external_url)
+    }
+
+    /// API key token sent by client subcommands, separate from the keys accepted by the server.
+    pub fn client_api_key(&self) -> Option<&str> {
+        self.client_api_key.as_ref().map(String::as_str)
+    }
+
+    pub fn load_api_key_file(&mut self) -> io::Result<()> {
+        if let Some(ref api_key_file) = self.api_key_file {
+            let mut api_keys = auth::load_api_key_file(api_key_file)?;
+            self.api_keys.append(&mut api_keys);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printed_config_grants_no_api_key() {
+        let cfg = QuiViveConfig::new()
+            .with_api_key(ApiKey::parse("s3cr3t:read,write").unwrap())
+            .with_api_key(ApiKey::parse("0th3r").unwrap());
+        let printed = cfg.to_toml();
+        assert!(!printed.contains("s3cr3t") && !printed.contains("0th3r"));
+
+        let mut reloaded = QuiViveConfig::new();
+        assert!(reloaded.load_toml(&printed).is_err());
+        assert!(reloaded.api_keys.is_empty());
+    }
+
+    #[test]
+    fn printed_config_rejects_redacted_secrets() {
+        let mut cfg = QuiViveConfig::new();
+        cfg.redis_password = Some("p4ssw0rd".to_string());
+        cfg.client_api_key = Some("s3cr3t".to_string());
+        let printed = cfg.to_toml();
+
+        let mut reloaded = QuiViveConfig::new();
+        assert!(reloaded.load_toml(&printed).is_err());
+        assert_eq!(reloaded.redis_password, None);
+        assert_eq!(reloaded.client_api_key, None);
+
+        // without secrets, the printed configuration loads back as it is
+        let printed = QuiViveConfig::new().to_toml();
+        let mut reloaded = QuiViveConfig::new();
+        reloaded.load_toml(&printed).unwrap();
+        assert_eq!(reloaded.to_toml(), printed);
     }
 }
@@ -0,0 +1,66 @@
+use url::Url;
+
+use crate::QuiViveConfig;
+
+fn matches_domain(host: &str, domain: &str) -> bool {
+    let domain = domain.trim_start_matches('.').to_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+/// Parses a redirect destination URL, checking it against the configured scheme and domain lists.
+pub fn parse_destination(cfg: &QuiViveConfig, value: &str) -> Result<Url, String> {
+    let url = Url::parse(value.trim()).map_err(|err| format!("invalid url: {}", err))?;
+
+    if !cfg.allowed_schemes.iter().any(|scheme| scheme.eq_ignore_ascii_case(url.scheme())) {
+        return Err(format!("scheme not allowed: {}", url.scheme()));
+    }
+
+    let host = match url.host_str() {
+        Some(host) => host.to_lowercase(),
+        None if cfg.allowed_domains.is_empty() && cfg.denied_domains.is_empty() => return Ok(url),
+        None => return Err("url has no host".to_string()),
+    };
+
+    if cfg.denied_domains.iter().any(|domain| matches_domain(&host, domain)) {
+        return Err(format!("domain denied: {}", host));
+    }
+
+    if !cfg.allowed_domains.is_empty() && !cfg.allowed_domains.iter().any(|domain| matches_domain(&host, domain)) {
+        return Err(format!("domain not allowed: {}", host));
+    }
+
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_schemes_not_allowed() {
+        let cfg = QuiViveConfig::new();
+        assert!(parse_destination(&cfg, "https://contoso.com/").is_ok());
+        assert!(parse_destination(&cfg, " HTTP://contoso.com/ ").is_ok());
+        assert!(parse_destination(&cfg, "javascript:alert(1)").is_err());
+        assert!(parse_destination(&cfg, "data:text/html,<script>alert(1)</script>").is_err());
+        assert!(parse_destination(&cfg, "file:///etc/passwd").is_err());
+        assert!(parse_destination(&cfg, "contoso.com").is_err());
+    }
+
+    #[test]
+    fn rejects_hosts_not_allowed() {
+        let mut cfg = QuiViveConfig::new();
+        cfg.allowed_domains = vec!["contoso.com".to_string()];
+        cfg.denied_domains = vec![".evil.contoso.com".to_string()];
+
+        assert!(parse_destination(&cfg, "https://contoso.com/").is_ok());
+        assert!(parse_destination(&cfg, "https://www.Contoso.com/").is_ok());
+        assert!(parse_destination(&cfg, "https://evil.contoso.com/").is_err());
+        assert!(parse_destination(&cfg, "https://www.evil.contoso.com/").is_err());
+        assert!(parse_destination(&cfg, "https://notcontoso.com/").is_err());
+        assert!(parse_destination(&cfg, "https://contoso.com.evil.net/").is_err());
+
+        cfg.allowed_schemes.push("mailto".to_string());
+        assert!(parse_destination(&cfg, "mailto:ceo@contoso.com").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::str::FromStr;
+
+use rand::{thread_rng, Rng};
+use time;
+use uuid::Uuid;
+
+use crate::QuiViveConfig;
+use crate::cache::QuiViveCache;
+use crate::store::StoreError;
+
+/// Strategy used to generate the id of new keys, short URLs and invitations.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum IdGenerator {
+    Random, // random characters from the id charset, of the configured id length
+    Uuid, // random UUID (version 4)
+    Ulid, // time-sortable ULID
+    Nanoid, // 21 random url-safe characters
+    Sequential, // base62-encoded counter shared by all instances using the same cache
+    Words, // human-readable words like 'brave-otter-4217'
+}
+
+impl FromStr for IdGenerator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "random" => Ok(IdGenerator::Random),
+            "uuid" => Ok(IdGenerator::Uuid),
+            "ulid" => Ok(IdGenerator::Ulid),
+            "nanoid" => Ok(IdGenerator::Nanoid),
+            "sequential" => Ok(IdGenerator::Sequential),
+            "words" => Ok(IdGenerator::Words),
+            _ => Err(format!("invalid id generator: {}", s)),
+        }
+    }
+}
+
+impl IdGenerator {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            IdGenerator::Random => "random",
+            IdGenerator::Uuid => "uuid",
+            IdGenerator::Ulid => "ulid",
+            IdGenerator::Nanoid => "nanoid",
+            IdGenerator::Sequential => "sequential",
+            IdGenerator::Words => "words",
+        }
+    }
+
+    /// Generates a new id, with None if the configuration does not allow generating one.
+    pub fn generate(&self, cfg: &QuiViveConfig, cache: &QuiViveCache) -> Result<Option<String>, StoreError> {
+        match *self {
+            IdGenerator::Random => Ok(random_id(cfg.id_length, &cfg.id_charset)),
+            IdGenerator::Uuid => Ok(Some(Uuid::new_v4().to_hyphenated().to_string())),
+            IdGenerator::Ulid => Ok(Some(ulid())),
+            IdGenerator::Nanoid => Ok(random_id(NANOID_LENGTH, NANOID_CHARSET)),
+            IdGenerator::Sequential => Ok(Some(encode_base62(cache.next_sequence(SEQUENCE_NAME)?))),
+            IdGenerator::Words => Ok(Some(words())),
+        }
+    }
+}
+
+fn random_id(length: u32, charset: &str) -> Option<String> {
+    let mut rng = thread_rng();
+    let charset = charset.as_bytes();
+    (0..length).map(|_| Some(*rng.choose(charset)? as char)).collect()
+}
+
+const NANOID_LENGTH: u32 = 21;
+const NANOID_CHARSET: &str = "0123456789\
+            abcdefghijklmnopqrstuvwxyz\
+            ABCDEFGHIJKLMNOPQRSTUVWXYZ_-";
+
+const SEQUENCE_NAME: &str = "sequence";
+
+const BASE62_CHARSET: &[u8] = b"0123456789\
+            abcdefghijklmnopqrstuvwxyz\
+            ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+pub fn encode_base62(mut value: u64) -> String {
+    let mut digits = Vec::new();
+
+    loop {
+        digits.push(BASE62_CHARSET[(value % 62) as usize]);
+        value /= 62;
+        if value == 0 {
+            break;
+        }
+    }
+
+    digits.reverse();
+    String::from_utf8(digits).unwrap()
+}
+
+// Crockford's base32, as required by the ULID specification
+const CROCKFORD_CHARSET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Generates a ULID: a 48-bit millisecond timestamp followed by 80 random bits, in 26 characters.
+fn ulid() -> String {
+    let timespec = time::get_time();
+    let timestamp = (timespec.sec as u64) * 1000 + (timespec.nsec as u64) / 1_000_000;
+
+    let mut rng = thread_rng();
+    let randomness: u128 = (rng.gen::<u64>() as u128) << 16 | rng.gen::<u16>() as u128;
+    let value: u128 = (timestamp as u128 & 0xFFFF_FFFF_FFFF) << 80 | randomness;
+
+    // 26 characters of 5 bits each encode 130 bits, the two leading bits are always zero
+    (0..26).rev()
+        .map(|i| CROCKFORD_CHARSET[((value >> (i * 5)) & 0x1F) as usize] as char)
+        .collect()
+}
+
+pub fn is_ulid(id: &str) -> bool {
+    // the first character only holds 3 bits, since a ULID is 128 bits
+    id.len() == 26 &&
+        id.as_bytes()[0] <= b'7' &&
+        id.bytes().all(|c| CROCKFORD_CHARSET.contains(&c.to_ascii_uppercase()))
+}
+
+const ADJECTIVES: &[&str] = &[
+    "able", "bold", "brave", "bright", "calm", "clever", "cool", "crisp",
+    "daring", "eager", "fair", "fancy", "fast", "fierce", "fond", "gentle",
+    "glad", "golden", "grand", "happy", "honest", "jolly", "keen", "kind",
+    "lively", "lucky", "merry", "mighty", "neat", "nimble", "noble", "plucky",
+    "polite", "proud", "quick", "quiet", "rapid", "ready", "royal", "shiny",
+    "silent", "sleek", "smart", "snappy", "solid", "steady", "sunny", "swift",
+    "tidy", "true", "vivid", "warm", "wise", "witty", "zany", "zesty",
+];
+
+const ANIMALS: &[&str] = &[
+    "badger", "bear", "beaver", "bison", "cat", "cobra", "crane", "crow",
+    "deer", "dingo", "dog", "dolphin", "eagle", "falcon", "ferret", "finch",
+    "fox", "gecko", "goat", "goose", "hare", "hawk", "heron", "horse",
+    "ibis", "koala", "lemur", "lion", "llama", "lynx", "marten", "mole",
+    "moose", "newt", "otter", "owl", "panda", "parrot", "puffin", "quail",
+    "rabbit", "raven", "robin", "seal", "shark", "sloth", "swan", "tiger",
+    "toad", "trout", "turtle", "viper", "walrus", "whale", "wolf", "wombat",
+];
+
+// the number makes for about 31 million ids, such that collisions stay rare as keys accumulate
+const WORDS_NUMBER_RANGE: u32 = 10000;
+
+/// Generates a human-readable id like 'brave-otter-4217', which is easy to read aloud but also easy to guess.
+fn words() -> String {
+    let mut rng = thread_rng();
+    let adjective = rng.choose(ADJECTIVES).unwrap();
+    let animal = rng.choose(ANIMALS).unwrap();
+    let number = rng.gen_range(0, WORDS_NUMBER_RANGE);
+    format!("{}-{}-{}", adjective, animal, number)
+}
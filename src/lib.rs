@@ -0,0 +1,128 @@
+//! A key-value store and url shortener that is always on alert.
+//!
+//! The `qui_vive` library exposes the service used by the `qui-vive` binary, such that it
+//! can be embedded in another hyper server or started inside integration tests:
+//!
+//! ```no_run
+//! extern crate hyper;
+//! extern crate qui_vive;
+//!
+//! use std::sync::Arc;
+//! use qui_vive::{new_cache, Metrics, QuiViveConfig, QuiViveService};
+//!
+//! let cfg = QuiViveConfig::new()
+//!     .with_external_url("http://127.0.0.1:8080")
+//!     .with_memory_cache();
+//! let cache = new_cache(&cfg).unwrap();
+//! let metrics = Arc::new(Metrics::new());
+//!
+//! let address = "127.0.0.1:8080".parse().unwrap();
+//! let server = hyper::server::Http::new().bind(&address, move || {
+//!     Ok(QuiViveService::new(cfg.clone(), cache.clone(), metrics.clone()))
+//! }).unwrap();
+//! server.run().unwrap();
+//! ```
+
+#[macro_use]
+extern crate hyper;
+
+extern crate futures;
+extern crate tokio_core;
+extern crate rustls;
+extern crate tokio_rustls;
+#[cfg(unix)]
+extern crate signal_hook;
+extern crate regex;
+extern crate rand;
+extern crate url;
+extern crate time;
+extern crate base64;
+
+extern crate clap;
+
+extern crate redis;
+extern crate fs2;
+
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate toml;
+
+extern crate qui_vive_client;
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate lazy_static;
+
+use std::sync::Arc;
+
+pub mod auth;
+
+pub mod config;
+
+pub mod command;
+pub use command::Command;
+
+pub mod admin;
+pub use config::{QuiViveConfig, CustomIdFormat};
+
+pub mod store;
+pub use store::{Store, StoreError, Ttl, MemoryStore, RedisStore, FileStore};
+
+pub mod cache;
+pub use cache::{QuiViveCache, QuiViveEntry};
+
+mod api;
+
+pub mod metrics;
+pub use metrics::Metrics;
+
+pub mod idgen;
+
+mod destination;
+
+pub mod notify;
+
+mod watch;
+
+pub mod service;
+pub use service::QuiViveService;
+
+pub mod tls;
+
+/// Connects to the redis server of the configuration (localhost by default).
+pub fn open_redis_store(ref cfg: &config::QuiViveConfig) -> Result<RedisStore, StoreError> {
+    let redis_hostname = cfg.redis_hostname.as_ref().map_or("localhost", |x| { x.as_str() });
+    let redis_password = cfg.redis_password.as_ref().map(String::as_str);
+    RedisStore::open(redis_hostname, redis_password)
+}
+
+/// Creates the store selected by the configuration (memory by default).
+pub fn new_store(ref cfg: &config::QuiViveConfig) -> Result<Arc<Store>, StoreError> {
+    let cache_type = cfg.cache_type.as_ref().map_or("memory", |x| { x.as_str() });
+    info!("creating {} store", cache_type);
+    match cache_type.as_ref() {
+        "redis" => {
+            Ok(Arc::new(open_redis_store(cfg)?))
+        }
+        "memory" => {
+            Ok(Arc::new(MemoryStore::new()))
+        }
+        "file" => {
+            let data_dir = cfg.data_dir.as_ref().map_or("data", |x| { x.as_str() });
+            Ok(Arc::new(FileStore::new(data_dir)?))
+        }
+        _ => {
+            Err(StoreError::Other("invalid cache type".to_string()))
+        }
+    }
+}
+
+/// Creates the cache selected by the configuration (memory by default).
+pub fn new_cache(ref cfg: &config::QuiViveConfig) -> Result<QuiViveCache, StoreError> {
+    new_store(cfg).map(QuiViveCache::new)
+}
@@ -1,76 +1,136 @@
 #[macro_use]
-extern crate hyper;
-
-extern crate futures;
-extern crate regex;
-extern crate rand;
-extern crate url;
-extern crate time;
-
+extern crate log;
 #[macro_use]
 extern crate clap;
+extern crate env_logger;
+extern crate hyper;
+extern crate qui_vive;
 
-extern crate mouscache;
-#[macro_use]
-extern crate mouscache_derive;
+use std::process;
+use std::sync::Arc;
 
-#[macro_use]
-extern crate log;
-extern crate env_logger;
+use clap::App;
 
-#[macro_use]
-extern crate lazy_static;
-
-use hyper::{Uri};
-use std::net::{SocketAddr};
-
-mod config;
-use config::QuiViveConfig;
-use config::CustomIdFormat;
-
-mod service;
-use service::QuiViveService;
-
-fn new_cache(ref cfg: &config::QuiViveConfig) -> std::result::Result<mouscache::Cache, mouscache::CacheError> {
-    let cache_type = cfg.cache_type.as_ref().map_or("memory", |x| { x.as_str() });
-    info!("creating {} cache", cache_type);
-    match cache_type.as_ref() {
-        "redis" => {
-            let redis_hostname = cfg.redis_hostname.as_ref().map_or("localhost", |x| { x.as_str() });
-            let redis_password = cfg.redis_password.as_ref().map(String::as_str);
-            mouscache::redis(redis_hostname, redis_password, None)
-        }
-        "memory" => {
-            Ok(mouscache::memory())
-        }
-        _ => {
-            Err(mouscache::CacheError::Other("invalid cache type".to_string()))
-        }
+use qui_vive::{config, new_cache, tls, Command, Metrics, QuiViveConfig, QuiViveService};
+use qui_vive::tls::TlsConfig;
+
+fn exit_with_errors(errors: &[String]) -> ! {
+    for err in errors {
+        eprintln!("error: {}", err);
     }
+    process::exit(2);
 }
 
 fn main() {
     env_logger::init();
 
+    let yaml = load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml).version(crate_version!()).get_matches();
+
     let mut cfg = QuiViveConfig::new();
-    cfg.load_cli();
-    cfg.load_env();
 
-    let cache = new_cache(&cfg).unwrap();
-    let url: Uri = cfg.listener_url.parse().unwrap();
-    let address: SocketAddr = url.authority().unwrap().parse().unwrap();
+    if let Err(err) = cfg.load(&matches) {
+        exit_with_errors(&[err]);
+    }
+
+    let command = match Command::from_matches(&matches) {
+        Ok(command) => command,
+        Err(err) => exit_with_errors(&[err]),
+    };
+
+    // keys from the api key file are part of the effective configuration
+    let api_key_file_error = cfg.load_api_key_file().err()
+        .map(|err| format!("failed to load api key file: {}", err));
+
+    if cfg.print_config {
+        if let Some(err) = api_key_file_error {
+            exit_with_errors(&[err]);
+        }
+
+        print!("{}", cfg.to_toml());
+        return;
+    }
+
+    // subcommands do not start a server, so the server options are not validated
+    if let Some(command) = command {
+        if let Some(err) = api_key_file_error {
+            exit_with_errors(&[err]);
+        }
+
+        if let Err(err) = command.run(&cfg) {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut errors = cfg.validate();
+    errors.extend(api_key_file_error);
+
+    let tls_config = match (cfg.listener_url.starts_with("https:"), &cfg.tls_cert, &cfg.tls_key) {
+        (true, &Some(ref tls_cert), &Some(ref tls_key)) => {
+            match TlsConfig::load(tls_cert, tls_key) {
+                Ok(tls_config) => Some(tls_config),
+                Err(err) => {
+                    errors.push(err);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    if !errors.is_empty() {
+        exit_with_errors(&errors);
+    }
+
+    if cfg.api_keys.is_empty() {
+        warn!("no api keys configured, authentication is disabled");
+    }
+
+    // the cache is probed such that an unreachable redis server is reported at startup
+    let cache = match new_cache(&cfg).and_then(|cache| cache.get("health".to_string()).map(|_| cache)) {
+        Ok(cache) => cache,
+        Err(err) => exit_with_errors(&[format!("failed to open the cache: {:?}", err)]),
+    };
+
+    // both addresses were checked by the validation
+    let address = config::parse_listener_url(&cfg.listener_url).unwrap();
+    let redirect = cfg.tls_redirect_url.as_ref().map(|redirect_url| {
+        (config::parse_listener_url(redirect_url).unwrap(), cfg.external_url.clone())
+    });
+
+    if let Some(ref tls_config) = tls_config {
+        if let Err(err) = tls_config.reload_on_sighup() {
+            warn!("failed to register SIGHUP handler, certificates will not be reloaded: {}", err);
+        }
+    }
+
+    let metrics = Arc::new(Metrics::new());
 
     let new_service = move || {
-        Ok(QuiViveService {
-            cfg: cfg.clone(),
-            cache: cache.clone(),
-        })
+        Ok(QuiViveService::new(cfg.clone(), cache.clone(), metrics.clone()))
     };
 
-    let server = hyper::server::Http::new()
-        .bind(&address, new_service)
-        .unwrap();
+    let result = if let Some(tls_config) = tls_config {
+        info!("running qui-vive at {} with tls", address);
+        tls::serve(address, new_service, tls_config, redirect).map_err(|err| err.to_string())
+    } else {
+        if redirect.is_some() {
+            warn!("ignoring tls redirect url, the listener url is not https");
+        }
+
+        hyper::server::Http::new()
+            .sleep_on_errors(true)
+            .bind(&address, new_service)
+            .and_then(|server| {
+                info!("running qui-vive at {}", address);
+                server.run()
+            })
+            .map_err(|err| err.to_string())
+    };
 
-    info!("running qui-vive at {}", address);
-    server.run().unwrap();
+    if let Err(err) = result {
+        exit_with_errors(&[format!("failed to listen on {}: {}", address, err)]);
+    }
 }
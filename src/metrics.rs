@@ -0,0 +1,162 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use hyper::Method;
+
+// upper bounds of the request latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+const METHODS: [&str; 5] = ["GET", "POST", "PUT", "DELETE", "OTHER"];
+
+#[derive(Clone, Copy)]
+pub enum Endpoint {
+    Key,
+    Url,
+    Inv,
+}
+
+impl Endpoint {
+    fn label(&self) -> &'static str {
+        match *self {
+            Endpoint::Key => "/key",
+            Endpoint::Url => "/url",
+            Endpoint::Inv => "/inv",
+        }
+    }
+}
+
+const ENDPOINTS: [Endpoint; 3] = [Endpoint::Key, Endpoint::Url, Endpoint::Inv];
+
+struct Histogram {
+    buckets: Vec<AtomicUsize>,
+    count: AtomicUsize,
+    sum_micros: AtomicUsize,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Histogram {
+            buckets: LATENCY_BUCKETS.iter().map(|_| AtomicUsize::new(0)).collect(),
+            count: AtomicUsize::new(0),
+            sum_micros: AtomicUsize::new(0),
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9;
+
+        for (bucket, upper_bound) in self.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+            if seconds <= *upper_bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add((seconds * 1e6) as usize, Ordering::Relaxed);
+    }
+}
+
+/// Process-wide counters, rendered in the Prometheus text exposition format.
+pub struct Metrics {
+    creations: Vec<AtomicUsize>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    redirects: AtomicUsize,
+    payload_too_large: AtomicUsize,
+    backend_errors: AtomicUsize,
+    latency: Vec<Histogram>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            creations: ENDPOINTS.iter().map(|_| AtomicUsize::new(0)).collect(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            redirects: AtomicUsize::new(0),
+            payload_too_large: AtomicUsize::new(0),
+            backend_errors: AtomicUsize::new(0),
+            latency: METHODS.iter().map(|_| Histogram::new()).collect(),
+        }
+    }
+
+    pub fn created(&self, endpoint: Endpoint) {
+        self.creations[endpoint as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn redirect(&self) {
+        self.redirects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn payload_too_large(&self) {
+        self.payload_too_large.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn backend_error(&self) {
+        self.backend_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe_latency(&self, method: &Method, duration: Duration) {
+        let index = match *method {
+            Method::Get => 0,
+            Method::Post => 1,
+            Method::Put => 2,
+            Method::Delete => 3,
+            _ => 4,
+        };
+        self.latency[index].observe(duration);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# HELP quivive_creations_total Number of entries created, per endpoint.");
+        let _ = writeln!(out, "# TYPE quivive_creations_total counter");
+        for endpoint in ENDPOINTS.iter() {
+            let _ = writeln!(out, "quivive_creations_total{{endpoint=\"{}\"}} {}",
+                endpoint.label(), self.creations[*endpoint as usize].load(Ordering::Relaxed));
+        }
+
+        let _ = writeln!(out, "# HELP quivive_lookups_total Number of entry lookups, per result.");
+        let _ = writeln!(out, "# TYPE quivive_lookups_total counter");
+        let _ = writeln!(out, "quivive_lookups_total{{result=\"hit\"}} {}", self.hits.load(Ordering::Relaxed));
+        let _ = writeln!(out, "quivive_lookups_total{{result=\"miss\"}} {}", self.misses.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP quivive_redirects_total Number of redirects served.");
+        let _ = writeln!(out, "# TYPE quivive_redirects_total counter");
+        let _ = writeln!(out, "quivive_redirects_total {}", self.redirects.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP quivive_payload_too_large_total Number of requests rejected for exceeding the maximum value size.");
+        let _ = writeln!(out, "# TYPE quivive_payload_too_large_total counter");
+        let _ = writeln!(out, "quivive_payload_too_large_total {}", self.payload_too_large.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP quivive_backend_errors_total Number of cache backend errors.");
+        let _ = writeln!(out, "# TYPE quivive_backend_errors_total counter");
+        let _ = writeln!(out, "quivive_backend_errors_total {}", self.backend_errors.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP quivive_request_duration_seconds Request latency, per method.");
+        let _ = writeln!(out, "# TYPE quivive_request_duration_seconds histogram");
+        for (method, histogram) in METHODS.iter().zip(self.latency.iter()) {
+            for (bucket, upper_bound) in histogram.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+                let _ = writeln!(out, "quivive_request_duration_seconds_bucket{{method=\"{}\",le=\"{}\"}} {}",
+                    method, upper_bound, bucket.load(Ordering::Relaxed));
+            }
+            let count = histogram.count.load(Ordering::Relaxed);
+            let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+            let _ = writeln!(out, "quivive_request_duration_seconds_bucket{{method=\"{}\",le=\"+Inf\"}} {}", method, count);
+            let _ = writeln!(out, "quivive_request_duration_seconds_sum{{method=\"{}\"}} {}", method, sum);
+            let _ = writeln!(out, "quivive_request_duration_seconds_count{{method=\"{}\"}} {}", method, count);
+        }
+
+        out
+    }
+}
@@ -0,0 +1,159 @@
+//! Change notifications for requests waiting on entries.
+//!
+//! Listeners are registered on a store key, and called on a single dispatcher thread when the key
+//! changes or when their deadline passes. Changes made through the cache are notified in-process,
+//! and changes made by other processes are notified by the store when it is shared (e.g. redis).
+
+use std::collections::HashSet;
+use std::cmp;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::store::Store;
+
+/// Maximum number of listeners, such that waiting requests cannot exhaust the server.
+pub const MAX_LISTENERS: usize = 1000;
+
+// closed listeners are dropped at least this often, even when nothing changes
+const TICK_INTERVAL_MS: u64 = 1000;
+
+/// Request waiting on a key.
+pub trait Listener: Send {
+    /// Called when the key changed or the deadline passed, returning the next deadline or None to stop listening.
+    fn notify(&mut self, now: Instant) -> Option<Instant>;
+
+    /// Returns true when the listener can be dropped, e.g. because the client went away.
+    fn is_closed(&mut self) -> bool;
+}
+
+struct Registration {
+    key: String,
+    deadline: Instant,
+    listener: Box<Listener>,
+}
+
+struct State {
+    changed: HashSet<String>,
+    registrations: Vec<Registration>,
+    active: usize, // registrations, including the ones being notified
+}
+
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+    started: Once,
+}
+
+#[derive(Clone)]
+pub struct Notifier {
+    shared: Arc<Shared>,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Notifier {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    changed: HashSet::new(),
+                    registrations: Vec::new(),
+                    active: 0,
+                }),
+                condvar: Condvar::new(),
+                started: Once::new(),
+            }),
+        }
+    }
+
+    /// Wakes up the listeners of a key.
+    pub fn changed(&self, key: &str) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.changed.insert(key.to_string());
+        self.shared.condvar.notify_one();
+    }
+
+    /// Registers a listener, which is notified right away and then on every change of the key.
+    /// The listener is given back if there are too many listeners already.
+    pub fn listen(&self, store: &Arc<Store>, key: &str, listener: Box<Listener>) -> Result<(), Box<Listener>> {
+        self.start(store);
+
+        let mut state = self.shared.state.lock().unwrap();
+
+        if state.active >= MAX_LISTENERS {
+            return Err(listener);
+        }
+
+        state.active += 1;
+        state.registrations.push(Registration {
+            key: key.to_string(),
+            deadline: Instant::now(),
+            listener: listener,
+        });
+        self.shared.condvar.notify_one();
+        Ok(())
+    }
+
+    // the dispatcher thread only runs once something is waiting, such that short-lived tools don't start it
+    fn start(&self, store: &Arc<Store>) {
+        self.shared.started.call_once(|| {
+            let notifier = self.clone();
+            if let Err(err) = store.listen(Box::new(move |key| notifier.changed(key))) {
+                warn!("failed to listen for changes from other instances: {}", err);
+            }
+
+            let notifier = self.clone();
+            thread::spawn(move || notifier.dispatch());
+        });
+    }
+
+    fn dispatch(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+
+        loop {
+            let now = Instant::now();
+            let changed = mem::replace(&mut state.changed, HashSet::new());
+
+            let mut due = Vec::new();
+            let mut waiting = Vec::new();
+            for mut registration in state.registrations.drain(..) {
+                if registration.listener.is_closed() {
+                    continue;
+                }
+                if registration.deadline <= now || changed.contains(&registration.key) {
+                    due.push(registration);
+                } else {
+                    waiting.push(registration);
+                }
+            }
+            state.active = waiting.len() + due.len();
+            state.registrations = waiting;
+
+            // listeners access the store, which must not block changes from being notified
+            if !due.is_empty() {
+                drop(state);
+                let mut notified = Vec::new();
+                for mut registration in due {
+                    if let Some(deadline) = registration.listener.notify(Instant::now()) {
+                        registration.deadline = deadline;
+                        notified.push(registration);
+                    }
+                }
+                state = self.shared.state.lock().unwrap();
+                state.registrations.extend(notified);
+                state.active = state.registrations.len();
+                continue;
+            }
+
+            if !state.changed.is_empty() {
+                continue;
+            }
+
+            let tick = Duration::from_millis(TICK_INTERVAL_MS);
+            let timeout = state.registrations.iter()
+                .map(|registration| if registration.deadline > now { registration.deadline - now } else { Duration::from_secs(0) })
+                .fold(tick, cmp::min);
+            state = self.shared.condvar.wait_timeout(state, timeout).unwrap().0;
+        }
+    }
+}
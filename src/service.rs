@@ -1,12 +1,13 @@
-
-use mouscache;
-
 use hyper;
 use hyper::{Body, StatusCode, mime};
-use hyper::Method::{Get, Post, Delete};
-use hyper::header::{ContentType, Location};
+use hyper::Method::{Get, Post, Put, Delete};
+use hyper::header::{CacheControl, CacheDirective, ContentDisposition, ContentType, DispositionType, IfNoneMatch, Location, Referer, UserAgent};
 use hyper::server::{Request, Response, Service};
 
+use std::cmp;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use futures;
 use futures::Future;
 use futures::stream::{Stream};
@@ -15,12 +16,21 @@ use rand::{thread_rng, Rng};
 use regex::Regex;
 use time;
 
-use uuid::{Uuid};
+use url::form_urlencoded;
 
-use url::{Url};
+use serde::Serialize;
+use serde_json;
 
 use crate::QuiViveConfig;
-use crate::CustomIdFormat;
+use crate::config;
+use crate::cache::{QuiViveCache, QuiViveEntry, QuiViveStats, Click, Refused, Update, KIND_KEY, KIND_URL, KIND_INV};
+use crate::store::StoreError;
+use crate::api::{EntryInfo, StatsInfo};
+use crate::auth;
+use crate::destination;
+use crate::idgen::IdGenerator;
+use crate::watch;
+use crate::metrics::{Metrics, Endpoint};
 
 static NOINDEX: &str = "noindex";
 header! { (XRobotsTag, "X-Robots-Tag") => [String] } // noindex
@@ -28,16 +38,45 @@ header! { (XRobotsTag, "X-Robots-Tag") => [String] } // noindex
 static NOSNIFF: &str = "nosniff";
 header! { (XContentTypeOptions, "X-Content-Type-Options") => [String] } // nosniff
 
+static SANDBOX: &str = "sandbox";
+header! { (ContentSecurityPolicy, "Content-Security-Policy") => [String] } // sandbox
+
+/// Content types that browsers cannot execute, which are the only ones served inline.
+static INLINE_CONTENT_TYPES: &[(&str, &str)] = &[
+    ("text", "plain"),
+    ("application", "json"),
+    ("application", "octet-stream"),
+    ("image", "png"),
+    ("image", "jpeg"),
+    ("image", "gif"),
+    ("image", "webp"),
+];
+
 header! { (QuiViveDstUrl, "QuiVive-DstUrl") => [String] }
 header! { (QuiViveIdParam, "QuiVive-IdParam") => [String] }
 header! { (QuiViveExpiration, "QuiVive-Expiration") => [String] }
+header! { (QuiViveMaxReads, "QuiVive-MaxReads") => [String] }
+header! { (QuiViveOwned, "QuiVive-Owned") => [String] }
+header! { (QuiViveRedirectStatus, "QuiVive-RedirectStatus") => [String] }
+header! { (QuiViveOwnerToken, "QuiVive-OwnerToken") => [String] }
+header! { (QuiViveIdGenerator, "QuiVive-IdGenerator") => [String] }
+
+const OWNER_TOKEN_LENGTH: usize = 32;
+const MAX_ID_ATTEMPTS: usize = 5;
 
-#[derive(Cacheable, Clone, Debug)]
-#[cache(rename="QuiVive")] // use 'QuiVive' prefix
-struct QuiViveEntry {
-    id: String,
-    val: String,
-    url: String,
+static PROMETHEUS_TEXT: &str = "text/plain; version=0.0.4; charset=utf-8";
+static EVENT_STREAM: &str = "text/event-stream";
+
+/// Paths that cannot be claimed as short URL aliases.
+static RESERVED_ALIASES: &[&str] = &["health", "key", "url", "inv", "api", "metrics"];
+
+fn is_valid_alias(alias: &str) -> bool {
+    lazy_static! {
+        static ref RE_ALIAS: Regex = Regex::new(r"^[A-Za-z0-9][A-Za-z0-9_-]{0,63}$").unwrap();
+    }
+
+    RE_ALIAS.is_match(alias) &&
+        !RESERVED_ALIASES.iter().any(|reserved| reserved.eq_ignore_ascii_case(alias))
 }
 
 pub fn get_timestamp() -> u32 {
@@ -45,19 +84,133 @@ pub fn get_timestamp() -> u32 {
     timespec.sec as u32
 }
 
+fn request_owner_token(request: &Request) -> Option<String> {
+    request.headers().get::<QuiViveOwnerToken>().map(|x| x.to_string())
+}
+
+fn with_owner_token(mut response: Response, entry: &QuiViveEntry) -> Response {
+    if entry.is_owned() {
+        response.headers_mut().set(QuiViveOwnerToken(entry.owner_token.clone()));
+    }
+    response
+}
+
+fn is_inline_content_type(content_type: &mime::Mime) -> bool {
+    INLINE_CONTENT_TYPES.iter().any(|&(type_, subtype)| {
+        content_type.type_() == type_ && content_type.subtype() == subtype
+    })
+}
+
+// curl -d and HTML forms send text as form data, which would otherwise be served as an attachment, so it
+// is stored as plain text like values without a Content-Type
+fn request_content_type(request: &Request) -> String {
+    match request.headers().get::<ContentType>() {
+        Some(&ContentType(ref content_type)) if content_type.type_() != mime::APPLICATION ||
+            content_type.subtype() != mime::WWW_FORM_URLENCODED => content_type.to_string(),
+        _ => mime::TEXT_PLAIN_UTF_8.to_string(),
+    }
+}
+
+fn value_response(entry: &QuiViveEntry) -> Response {
+    let data = match entry.data() {
+        Ok(data) => data,
+        Err(err) => {
+            warn!("failed to decode value of {}: {}", entry.id, err);
+            return Response::new()
+                .with_status(StatusCode::InternalServerError);
+        }
+    };
+
+    let content_type = entry.content_type.parse::<mime::Mime>()
+        .unwrap_or(mime::TEXT_PLAIN_UTF_8);
+
+    // values are served from the service origin, so active content like HTML or SVG must never run there
+    let mut response = Response::new()
+        .with_status(StatusCode::Ok)
+        .with_header(XContentTypeOptions(NOSNIFF.to_string()))
+        .with_header(ContentSecurityPolicy(SANDBOX.to_string()))
+        .with_header(XRobotsTag(NOINDEX.to_string()));
+
+    if !is_inline_content_type(&content_type) {
+        response.headers_mut().set(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: Vec::new(),
+        });
+    }
+
+    response
+        .with_header(ContentType(content_type))
+        .with_body(data)
+}
+
+fn refused_response(refused: Refused<StatusCode>) -> Response {
+    Response::new()
+        .with_status(match refused {
+            Refused::Missing => StatusCode::NotFound,
+            Refused::NotOwner => StatusCode::Forbidden,
+            Refused::Kept(status) => status,
+        })
+}
+
+// the management API does not consume reads, so it must not reveal values limited to a number of reads
+fn entry_info(entry: &QuiViveEntry, now: u32) -> EntryInfo {
+    let info = EntryInfo::new(entry, now);
+    if entry.max_reads > 0 { info.without_value() } else { info }
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response {
+    match serde_json::to_string(value) {
+        Ok(json) => {
+            Response::new()
+                .with_status(StatusCode::Ok)
+                .with_header(ContentType(mime::APPLICATION_JSON))
+                .with_header(XContentTypeOptions(NOSNIFF.to_string()))
+                .with_header(XRobotsTag(NOINDEX.to_string()))
+                .with_body(json)
+        }
+        Err(_) => {
+            Response::new()
+                .with_status(StatusCode::InternalServerError)
+        }
+    }
+}
+
 pub struct QuiViveService {
     pub cfg: QuiViveConfig,
-    pub cache: mouscache::Cache,
+    pub cache: QuiViveCache,
+    pub metrics: Arc<Metrics>,
+}
+
+/// Inserts a new entry under a generated id, retrying with another id on collision.
+/// Returns None if no free id could be found within MAX_ID_ATTEMPTS.
+fn insert_new<F>(cache: &QuiViveCache, cfg: &QuiViveConfig, id_generator: IdGenerator, expiration: Option<usize>, new_entry: F)
+    -> Result<Option<QuiViveEntry>, StoreError> where F: Fn(String) -> QuiViveEntry
+{
+    for _ in 0..MAX_ID_ATTEMPTS {
+        let id = match id_generator.generate(cfg, cache)? {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        let entry = new_entry(id.clone());
+
+        if cache.insert_if_absent(id.clone(), entry.clone(), expiration)? {
+            return Ok(Some(entry));
+        }
+
+        debug!("generated id {} is already taken, retrying", id);
+    }
+
+    Ok(None)
 }
 
 impl QuiViveService {
-    fn gen_id(&self) -> Option<String> {
-        let mut rng = thread_rng();
-        let id_length = self.cfg.id_length;
-        let id_charset = &self.cfg.id_charset.as_ref();
-        let id: Option<String> = (0..id_length).map(|_|
-            Some(*rng.choose(id_charset)? as char)).collect();
-        id
+    pub fn new(cfg: QuiViveConfig, cache: QuiViveCache, metrics: Arc<Metrics>) -> Self {
+        QuiViveService {
+            cfg: cfg,
+            cache: cache,
+            metrics: metrics,
+        }
     }
 
     fn get_expiration(&self, request: &Request) -> Option<usize> {
@@ -72,40 +225,128 @@ impl QuiViveService {
         }
         self.cfg.default_expiration.map(|x| x as usize)
     }
-}
 
-impl Service for QuiViveService {
-    type Request = Request;
-    type Response = Response;
-    type Error = hyper::Error;
-    type Future = Box<Future<Item=Response<Body>, Error=hyper::Error>>;
+    fn new_owner_token(&self, request: &Request) -> Option<String> {
+        let owned = request.headers().get::<QuiViveOwned>()
+            .map_or(false, |x| x.to_string().eq_ignore_ascii_case("true"));
 
-    fn call(&self, request: Request) -> Self::Future {
+        if owned {
+            let mut rng = thread_rng();
+            Some(rng.gen_ascii_chars().take(OWNER_TOKEN_LENGTH).collect())
+        } else {
+            None
+        }
+    }
+
+    /// Gets the id generator from the QuiVive-IdGenerator header, with Err if it is invalid or not allowed.
+    fn get_id_generator(&self, request: &Request) -> Result<IdGenerator, String> {
+        match request.headers().get::<QuiViveIdGenerator>() {
+            Some(id_generator) => {
+                let id_generator = id_generator.parse::<IdGenerator>()?;
+                if !self.cfg.is_allowed_id_generator(id_generator) {
+                    return Err(format!("id generator not allowed: {}", id_generator.as_str()));
+                }
+                Ok(id_generator)
+            }
+            None => Ok(self.cfg.id_generator),
+        }
+    }
+
+    /// Gets the redirect status code from the QuiVive-RedirectStatus header, with Err if it is invalid.
+    fn get_redirect_status(&self, request: &Request) -> Result<Option<u16>, ()> {
+        match request.headers().get::<QuiViveRedirectStatus>() {
+            Some(redirect_status) => config::parse_redirect_status(redirect_status).map(Some).ok_or(()),
+            None => Ok(None),
+        }
+    }
+
+    fn redirect_response(&self, entry: &QuiViveEntry) -> Response {
+        let redirect_status = if entry.redirect_status != 0 {
+            entry.redirect_status
+        } else if entry.kind == KIND_INV {
+            self.cfg.inv_redirect_status
+        } else {
+            self.cfg.redirect_status
+        };
+
+        Response::new()
+            .with_status(StatusCode::try_from(redirect_status).unwrap_or(StatusCode::MovedPermanently))
+            .with_header(Location::new(entry.url.clone()))
+    }
+
+    fn record_click(&self, request: &Request, entry: &QuiViveEntry) {
+        let click = Click {
+            timestamp: get_timestamp(),
+            referrer: request.headers().get::<Referer>().map(|x| x.to_string()),
+            user_agent: request.headers().get::<UserAgent>().map(|x| x.to_string()),
+        };
+
+        let expiration = entry.ttl(click.timestamp).map(|x| x as usize);
+
+        if let Err(err) = self.cache.record_click(entry.id.clone(), click, expiration) {
+            self.metrics.backend_error();
+            warn!("failed to record click on {}: {:?}", entry.id, err);
+        }
+    }
+
+    /// Gets the number of seconds to wait for a key to be set from the 'wait' query parameter.
+    fn get_wait(&self, request: &Request) -> u32 {
+        let wait = request.query()
+            .and_then(|query| {
+                form_urlencoded::parse(query.as_bytes())
+                    .find(|&(ref name, _)| name == "wait")
+                    .and_then(|(_, value)| value.parse::<u32>().ok())
+            })
+            .unwrap_or(0);
+        cmp::min(wait, self.cfg.max_wait)
+    }
+
+    /// Gets the maximum number of reads from the QuiVive-MaxReads header (0 means unlimited), with Err if it is invalid.
+    fn get_max_reads(&self, request: &Request) -> Result<u32, ()> {
+        match request.headers().get::<QuiViveMaxReads>() {
+            Some(max_reads) => max_reads.to_string().trim().parse::<u32>().map_err(|_| ()),
+            None => Ok(0),
+        }
+    }
+}
+
+impl QuiViveService {
+    fn route(&self, request: Request) -> Box<Future<Item=Response<Body>, Error=hyper::Error>> {
 
         lazy_static! {
-            static ref RE_ID: Regex = Regex::new(r"^/([\w|-]+)$").unwrap();
+            static ref RE_ID: Regex = Regex::new(r"^/([\w-]+)$").unwrap();
             static ref RE_KEY: Regex = Regex::new(r"^/key$").unwrap();
-            static ref RE_KEY_ID: Regex = Regex::new(r"^/key/([\w|-]+)$").unwrap();
+            static ref RE_KEY_ID: Regex = Regex::new(r"^/key/([\w-]+)$").unwrap();
+            static ref RE_KEY_EVENTS: Regex = Regex::new(r"^/key/([\w-]+)/events$").unwrap();
             static ref RE_URL: Regex = Regex::new(r"^/url$").unwrap();
-            static ref RE_URL_ID: Regex = Regex::new(r"^/url/([\w|-]+)$").unwrap();
+            static ref RE_URL_ID: Regex = Regex::new(r"^/url/([\w-]+)$").unwrap();
             static ref RE_INV: Regex = Regex::new(r"^/inv$").unwrap();
-            static ref RE_INV_ID: Regex = Regex::new(r"^/inv/([\w|-]+)$").unwrap();
+            static ref RE_INV_ID: Regex = Regex::new(r"^/inv/([\w-]+)$").unwrap();
+            static ref RE_API_ENTRY_ID: Regex = Regex::new(r"^/api/v1/entries/([\w-]+)$").unwrap();
+            static ref RE_API_ENTRY_TTL: Regex = Regex::new(r"^/api/v1/entries/([\w-]+)/ttl$").unwrap();
+            static ref RE_API_ENTRY_STATS: Regex = Regex::new(r"^/api/v1/entries/([\w-]+)/stats$").unwrap();
         }
 
         let method = request.method().clone();
         let path = request.path().clone().to_owned();
 
+        if let Some(scope) = auth::required_scope(&method, &path) {
+            if let Err(response) = auth::authorize(&self.cfg.api_keys, &request, scope) {
+                return Box::new(futures::future::ok(response));
+            }
+        }
+
         match (method, path.as_str()) {
             (Get, "/health") => {
                 let id = "health".to_string();
                 let input = format!("{}", get_timestamp());
                 let expiration = self.get_expiration(&request);
 
-                let entry = QuiViveEntry { id: id.clone(), val: input.clone(), url: "".to_string() };
+                let entry = QuiViveEntry::new(id.clone(), KIND_KEY, input.clone(), "".to_string(), expiration);
                 let cache = self.cache.clone();
 
                 if let Ok(_) = cache.insert_with(id.clone(), entry.clone(), expiration) {
-                    match cache.get::<String, QuiViveEntry>(id.clone()) {
+                    match cache.get(id.clone()) {
                         Ok(Some(ref entry)) if entry.val.eq(&input) => {
                             Box::new(futures::future::ok(Response::new()
                                 .with_status(StatusCode::Ok)))
@@ -120,35 +361,74 @@ impl Service for QuiViveService {
                         .with_status(StatusCode::InternalServerError)))
                 }
             }
+            (Get, "/metrics") => {
+                Box::new(futures::future::ok(Response::new()
+                    .with_status(StatusCode::Ok)
+                    .with_header(ContentType(PROMETHEUS_TEXT.parse().unwrap()))
+                    .with_body(self.metrics.render())))
+            }
             (Post, ref x) if RE_KEY.is_match(x) => {
-                let id = self.gen_id().unwrap();
                 let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
                 let external_url = self.cfg.external_url.clone();
                 let expiration = self.get_expiration(&request);
+                let max_reads = match self.get_max_reads(&request) {
+                    Ok(max_reads) => max_reads,
+                    Err(_) => {
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::BadRequest)));
+                    }
+                };
+                let owner_token = self.new_owner_token(&request);
                 let max_value_size = self.cfg.max_value_size;
+                let content_type = Some(request_content_type(&request));
+                let cfg = self.cfg.clone();
+
+                let id_generator = match self.get_id_generator(&request) {
+                    Ok(id_generator) => id_generator,
+                    Err(err) => {
+                        debug!("rejecting request: {}", err);
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::BadRequest)));
+                    }
+                };
 
                 Box::new(request.body().concat2().map(move|body| {
                     if body.len() > max_value_size {
-                        Response::new()
-                            .with_status(StatusCode::PayloadTooLarge)
-                    } else if let Ok(value) = String::from_utf8(body.to_vec()) {
-                        let entry = QuiViveEntry { id: id.clone(), val: value, url: "".to_string() };
-                        let result = format!("{}/key/{}\n", external_url, id.clone());
+                        metrics.payload_too_large();
+                        return Response::new()
+                            .with_status(StatusCode::PayloadTooLarge);
+                    }
 
-                        if let Ok(_) = cache.insert_with(id.clone(), entry.clone(), expiration) {
-                            Response::new()
+                    let inserted = insert_new(&cache, &cfg, id_generator, expiration, |id| {
+                        QuiViveEntry::new(id, KIND_KEY, "".to_string(), "".to_string(), expiration)
+                            .with_content(body.to_vec(), content_type.clone())
+                            .with_max_reads(max_reads)
+                            .with_owner_token(owner_token.clone())
+                    });
+
+                    match inserted {
+                        Ok(Some(entry)) => {
+                            metrics.created(Endpoint::Key);
+                            let result = format!("{}/key/{}\n", external_url, entry.id);
+                            let response = Response::new()
                                 .with_status(StatusCode::Ok)
                                 .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
                                 .with_header(XContentTypeOptions(NOSNIFF.to_string()))
                                 .with_header(XRobotsTag(NOINDEX.to_string()))
-                                .with_body(result)
-                        } else {
+                                .with_body(result);
+                            with_owner_token(response, &entry)
+                        }
+                        Ok(None) => {
+                            warn!("failed to generate a free id after {} attempts", MAX_ID_ATTEMPTS);
+                            Response::new()
+                                .with_status(StatusCode::InternalServerError)
+                        }
+                        Err(_) => {
+                            metrics.backend_error();
                             Response::new()
                                 .with_status(StatusCode::InternalServerError)
                         }
-                    } else {
-                        Response::new()
-                            .with_status(StatusCode::BadRequest)
                     }
                 }))
             }
@@ -156,49 +436,113 @@ impl Service for QuiViveService {
                 let cap = RE_KEY_ID.captures(x).unwrap();
                 let id = cap[1].to_string();
                 let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
                 let external_url = self.cfg.external_url.clone();
                 let expiration = self.get_expiration(&request);
+                let max_reads = match self.get_max_reads(&request) {
+                    Ok(max_reads) => max_reads,
+                    Err(_) => {
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::BadRequest)));
+                    }
+                };
+                let owner_token = self.new_owner_token(&request);
+                let request_owner_token = request_owner_token(&request);
                 let max_value_size = self.cfg.max_value_size;
+                let content_type = Some(request_content_type(&request));
 
-                let bad_request = match self.cfg.custom_id_format {
-                    CustomIdFormat::None => true,
-                    CustomIdFormat::All => false,
-                    CustomIdFormat::Uuid => {
-                        if let Ok(_) = Uuid::parse_str(id.as_str()) {
-                            false
-                        } else {
-                            true
-                        }
-                    }
+                // "If-None-Match: *" only creates the key if it does not exist yet
+                let create_only = match request.headers().get::<IfNoneMatch>() {
+                    Some(&IfNoneMatch::Any) => true,
+                    _ => false,
                 };
 
-                if bad_request {
+                if !self.cfg.is_valid_custom_id(&id) {
                     return Box::new(futures::future::ok(Response::new()
                         .with_status(StatusCode::BadRequest)));
                 }
 
                 Box::new(request.body().concat2().map(move|body| {
                     if body.len() > max_value_size {
-                        Response::new()
-                            .with_status(StatusCode::PayloadTooLarge)
-                    } else if let Ok(value) = String::from_utf8(body.to_vec()) {
-                        let entry = QuiViveEntry { id: id.clone(), val: value, url: "".to_string() };
+                        metrics.payload_too_large();
+                        return Response::new()
+                            .with_status(StatusCode::PayloadTooLarge);
+                    }
+
+                    if create_only {
+                        let entry = QuiViveEntry::new(id.clone(), KIND_KEY, "".to_string(), "".to_string(), expiration)
+                            .with_content(body.to_vec(), content_type)
+                            .with_max_reads(max_reads)
+                            .with_owner_token(owner_token);
                         let result = format!("{}/key/{}\n", external_url, id.clone());
 
-                        if let Ok(_) = cache.insert_with(id.clone(), entry.clone(), expiration) {
-                            Response::new()
+                        return match cache.insert_if_absent(id.clone(), entry.clone(), expiration) {
+                            Ok(true) => {
+                                metrics.created(Endpoint::Key);
+                                let response = Response::new()
+                                    .with_status(StatusCode::Ok)
+                                    .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
+                                    .with_header(XContentTypeOptions(NOSNIFF.to_string()))
+                                    .with_header(XRobotsTag(NOINDEX.to_string()))
+                                    .with_body(result);
+                                with_owner_token(response, &entry)
+                            }
+                            Ok(false) => {
+                                Response::new()
+                                    .with_status(StatusCode::PreconditionFailed)
+                            }
+                            Err(_) => {
+                                metrics.backend_error();
+                                Response::new()
+                                    .with_status(StatusCode::InternalServerError)
+                            }
+                        };
+                    }
+
+                    // the owner check and the write are atomic, such that a concurrently created owned entry is never overwritten
+                    let updated = cache.update(id.clone(), |existing| {
+                        // short URLs and invitations sharing the id are never replaced by a key
+                        if existing.map_or(false, |existing| existing.kind != KIND_KEY) {
+                            return Update::Keep(StatusCode::Conflict);
+                        }
+
+                        // overwriting an entry with an owner requires its owner token, which is kept
+                        let owner_token = match existing {
+                            Some(existing) if !existing.is_owned_by(request_owner_token.as_ref().map(String::as_str)) => {
+                                return Update::Keep(StatusCode::Forbidden);
+                            }
+                            Some(existing) if existing.is_owned() => Some(existing.owner_token.clone()),
+                            _ => owner_token.clone(),
+                        };
+
+                        let entry = QuiViveEntry::new(id.clone(), KIND_KEY, "".to_string(), "".to_string(), expiration)
+                            .with_content(body.to_vec(), content_type.clone())
+                            .with_max_reads(max_reads)
+                            .with_owner_token(owner_token);
+                        Update::Put(entry, expiration)
+                    });
+                    let result = format!("{}/key/{}\n", external_url, id.clone());
+
+                    match updated {
+                        Ok(Ok(entry)) => {
+                            metrics.created(Endpoint::Key);
+                            let response = Response::new()
                                 .with_status(StatusCode::Ok)
                                 .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
                                 .with_header(XContentTypeOptions(NOSNIFF.to_string()))
                                 .with_header(XRobotsTag(NOINDEX.to_string()))
-                                .with_body(result)
-                        } else {
+                                .with_body(result);
+                            with_owner_token(response, &entry)
+                        }
+                        Ok(Err(status)) => {
+                            Response::new()
+                                .with_status(status)
+                        }
+                        Err(_) => {
+                            metrics.backend_error();
                             Response::new()
                                 .with_status(StatusCode::InternalServerError)
                         }
-                    } else {
-                        Response::new()
-                            .with_status(StatusCode::BadRequest)
                     }
                 }))
             }
@@ -207,65 +551,245 @@ impl Service for QuiViveService {
                 let id = cap[1].to_string();
 
                 let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
+                let wait = self.get_wait(&request);
 
-                match cache.get::<String, QuiViveEntry>(id.clone()) {
+                match cache.get_for_read(id.clone()) {
                     Ok(Some(entry)) => {
+                        metrics.hit();
+                        Box::new(futures::future::ok(value_response(&entry)))
+                    }
+                    Ok(None) if wait > 0 => {
+                        let waiting = match watch::wait_for_entry(cache, id, Duration::from_secs(wait as u64)) {
+                            Some(waiting) => waiting,
+                            None => {
+                                warn!("too many requests waiting for entries");
+                                return Box::new(futures::future::ok(Response::new()
+                                    .with_status(StatusCode::ServiceUnavailable)));
+                            }
+                        };
+
+                        Box::new(waiting.then(move |result| -> Result<Response, hyper::Error> {
+                            match result {
+                                Ok(Some(entry)) => {
+                                    metrics.hit();
+                                    Ok(value_response(&entry))
+                                }
+                                Ok(None) => {
+                                    metrics.miss();
+                                    Ok(Response::new()
+                                        .with_status(StatusCode::NotFound))
+                                }
+                                Err(_) => {
+                                    metrics.backend_error();
+                                    Ok(Response::new()
+                                        .with_status(StatusCode::NotFound))
+                                }
+                            }
+                        }))
+                    }
+                    Ok(_) => {
+                        metrics.miss();
                         Box::new(futures::future::ok(Response::new()
-                            .with_status(StatusCode::Ok)
-                            .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
-                            .with_header(XContentTypeOptions(NOSNIFF.to_string()))
-                            .with_header(XRobotsTag(NOINDEX.to_string()))
-                            .with_body(entry.val)
-                        ))
+                            .with_status(StatusCode::NotFound)))
                     }
-                    _ => {
+                    Err(_) => {
+                        metrics.backend_error();
                         Box::new(futures::future::ok(Response::new()
                             .with_status(StatusCode::NotFound)))
                     }
                 }
             }
+            (Get, ref x) if RE_KEY_EVENTS.is_match(x) => {
+                let cap = RE_KEY_EVENTS.captures(x).unwrap();
+                let id = cap[1].to_string();
+
+                let cache = self.cache.clone();
+
+                let body = match watch::watch_entry(cache, id) {
+                    Some(body) => body,
+                    None => {
+                        warn!("too many requests waiting for entries");
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::ServiceUnavailable)));
+                    }
+                };
+
+                Box::new(futures::future::ok(Response::new()
+                    .with_status(StatusCode::Ok)
+                    .with_header(ContentType(EVENT_STREAM.parse().unwrap()))
+                    .with_header(CacheControl(vec![CacheDirective::NoCache]))
+                    .with_header(XRobotsTag(NOINDEX.to_string()))
+                    .with_body(body)))
+            }
             (Delete, ref x) if RE_KEY_ID.is_match(x) => {
                 let cap = RE_KEY_ID.captures(x).unwrap();
                 let id = cap[1].to_string();
 
                 let cache = self.cache.clone();
-                let _ = cache.remove::<String, QuiViveEntry>(id.clone());
+                let metrics = self.metrics.clone();
+                let request_owner_token = request_owner_token(&request);
 
-                // always return 200 OK, even if the resource did not exist (already deleted)
-                Box::new(futures::future::ok(Response::new()
-                    .with_status(StatusCode::Ok)))
+                match cache.remove_owned(id.clone(), request_owner_token.as_ref().map(String::as_str)) {
+                    Ok(Err(Refused::NotOwner)) => {
+                        Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::Forbidden)))
+                    }
+                    Err(_) => {
+                        metrics.backend_error();
+                        Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::InternalServerError)))
+                    }
+                    _ => {
+                        // always return 200 OK, even if the resource did not exist (already deleted)
+                        Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::Ok)))
+                    }
+                }
             }
             (Post, ref x) if RE_URL.is_match(x) => {
-                let id = self.gen_id().unwrap();
                 let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
                 let external_url = self.cfg.external_url.clone();
                 let expiration = self.get_expiration(&request);
+                let owner_token = self.new_owner_token(&request);
                 let max_value_size = self.cfg.max_value_size;
+                let cfg = self.cfg.clone();
+
+                let id_generator = match self.get_id_generator(&request) {
+                    Ok(id_generator) => id_generator,
+                    Err(err) => {
+                        debug!("rejecting request: {}", err);
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::BadRequest)));
+                    }
+                };
+
+                let redirect_status = match self.get_redirect_status(&request) {
+                    Ok(redirect_status) => redirect_status,
+                    Err(_) => {
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::BadRequest)));
+                    }
+                };
 
                 Box::new(request.body().concat2().map(move|body| {
                     if body.len() > max_value_size {
+                        metrics.payload_too_large();
                         Response::new()
                             .with_status(StatusCode::PayloadTooLarge)
                     } else if let Ok(value) = String::from_utf8(body.to_vec()) {
-                        let url = value.clone();
+                        let url = match destination::parse_destination(&cfg, &value) {
+                            Ok(url) => url.to_string(),
+                            Err(err) => {
+                                debug!("rejecting destination url: {}", err);
+                                return Response::new()
+                                    .with_status(StatusCode::BadRequest);
+                            }
+                        };
 
-                        let entry = QuiViveEntry { id: id.clone(), val: "".to_string(), url: url };
-                        let result = format!("{}/{}\n", external_url, id);
+                        let inserted = insert_new(&cache, &cfg, id_generator, expiration, |id| {
+                            QuiViveEntry::new(id, KIND_URL, "".to_string(), url.clone(), expiration)
+                                .with_owner_token(owner_token.clone())
+                                .with_redirect_status(redirect_status)
+                        });
 
-                        if let Ok(_) = cache.insert_with(id.clone(), entry.clone(), expiration) {
-                            Response::new()
+                        match inserted {
+                            Ok(Some(entry)) => {
+                                metrics.created(Endpoint::Url);
+                                let result = format!("{}/{}\n", external_url, entry.id);
+                                let response = Response::new()
+                                    .with_status(StatusCode::Ok)
+                                    .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
+                                    .with_header(XContentTypeOptions(NOSNIFF.to_string()))
+                                    .with_header(XRobotsTag(NOINDEX.to_string()))
+                                    .with_body(result);
+                                with_owner_token(response, &entry)
+                            }
+                            Ok(None) => {
+                                warn!("failed to generate a free id after {} attempts", MAX_ID_ATTEMPTS);
+                                Response::new()
+                                    .with_status(StatusCode::InternalServerError)
+                            }
+                            Err(_) => {
+                                metrics.backend_error();
+                                Response::new()
+                                    .with_status(StatusCode::InternalServerError)
+                            }
+                        }
+                    } else {
+                        Response::new()
+                            .with_status(StatusCode::BadRequest)
+                    }
+                }))
+            }
+            (Post, ref x) if RE_URL_ID.is_match(x) => {
+                let cap = RE_URL_ID.captures(x).unwrap();
+                let id = cap[1].to_string();
+                let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
+                let external_url = self.cfg.external_url.clone();
+                let expiration = self.get_expiration(&request);
+                let owner_token = self.new_owner_token(&request);
+                let max_value_size = self.cfg.max_value_size;
+                let cfg = self.cfg.clone();
+
+                if !is_valid_alias(&id) {
+                    return Box::new(futures::future::ok(Response::new()
+                        .with_status(StatusCode::BadRequest)));
+                }
+
+                let redirect_status = match self.get_redirect_status(&request) {
+                    Ok(redirect_status) => redirect_status,
+                    Err(_) => {
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::BadRequest)));
+                    }
+                };
+
+                Box::new(request.body().concat2().map(move|body| {
+                    if body.len() > max_value_size {
+                        metrics.payload_too_large();
+                        return Response::new()
+                            .with_status(StatusCode::PayloadTooLarge);
+                    }
+
+                    let url = match String::from_utf8(body.to_vec()).map_err(|err| err.to_string())
+                        .and_then(|value| destination::parse_destination(&cfg, &value)) {
+                        Ok(url) => url.to_string(),
+                        Err(err) => {
+                            debug!("rejecting destination url: {}", err);
+                            return Response::new()
+                                .with_status(StatusCode::BadRequest);
+                        }
+                    };
+
+                    let entry = QuiViveEntry::new(id.clone(), KIND_URL, "".to_string(), url, expiration)
+                        .with_owner_token(owner_token)
+                        .with_redirect_status(redirect_status);
+                    let result = format!("{}/{}\n", external_url, id);
+
+                    // an alias is never overwritten, use PUT /url/{id} to change its destination
+                    match cache.insert_if_absent(id.clone(), entry.clone(), expiration) {
+                        Ok(true) => {
+                            metrics.created(Endpoint::Url);
+                            let response = Response::new()
                                 .with_status(StatusCode::Ok)
                                 .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
                                 .with_header(XContentTypeOptions(NOSNIFF.to_string()))
                                 .with_header(XRobotsTag(NOINDEX.to_string()))
-                                .with_body(result)
-                        } else {
+                                .with_body(result);
+                            with_owner_token(response, &entry)
+                        }
+                        Ok(false) => {
+                            Response::new()
+                                .with_status(StatusCode::Conflict)
+                        }
+                        Err(_) => {
+                            metrics.backend_error();
                             Response::new()
                                 .with_status(StatusCode::InternalServerError)
                         }
-                    } else {
-                        Response::new()
-                            .with_status(StatusCode::BadRequest)
                     }
                 }))
             }
@@ -274,61 +798,178 @@ impl Service for QuiViveService {
                 let id = cap[1].to_string();
 
                 let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
 
-                match cache.get::<String, QuiViveEntry>(id.clone()) {
+                match cache.get(id.clone()) {
                     Ok(Some(entry)) => {
+                        metrics.hit();
+                        metrics.redirect();
+                        self.record_click(&request, &entry);
+                        Box::new(futures::future::ok(self.redirect_response(&entry)))
+                    }
+                    Ok(_) => {
+                        metrics.miss();
                         Box::new(futures::future::ok(Response::new()
-                            .with_status(StatusCode::MovedPermanently)
-                            .with_header(Location::new(entry.url))
-                        ))
+                            .with_status(StatusCode::NotFound)))
                     }
-                    _ => {
+                    Err(_) => {
+                        metrics.backend_error();
                         Box::new(futures::future::ok(Response::new()
                             .with_status(StatusCode::NotFound)))
                     }
                 }
             }
+            (Put, ref x) if RE_URL_ID.is_match(x) => {
+                let cap = RE_URL_ID.captures(x).unwrap();
+                let id = cap[1].to_string();
+                let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
+                let external_url = self.cfg.external_url.clone();
+                let request_owner_token = request_owner_token(&request);
+                let max_value_size = self.cfg.max_value_size;
+                let cfg = self.cfg.clone();
+
+                Box::new(request.body().concat2().map(move|body| {
+                    if body.len() > max_value_size {
+                        metrics.payload_too_large();
+                        return Response::new()
+                            .with_status(StatusCode::PayloadTooLarge);
+                    }
+
+                    let url = match String::from_utf8(body.to_vec()).map_err(|err| err.to_string())
+                        .and_then(|value| destination::parse_destination(&cfg, &value)) {
+                        Ok(url) => url.to_string(),
+                        Err(err) => {
+                            debug!("rejecting destination url: {}", err);
+                            return Response::new()
+                                .with_status(StatusCode::BadRequest);
+                        }
+                    };
+
+                    let updated = cache.update_owned(id.clone(), request_owner_token.as_ref().map(String::as_str), |entry| {
+                        if entry.kind != KIND_URL {
+                            return Update::Keep(StatusCode::NotFound);
+                        }
+
+                        // the id, expiration and stats are kept, only the destination changes
+                        let mut entry = entry.clone();
+                        entry.url = url.clone();
+                        let expiration = entry.ttl(get_timestamp()).map(|x| cmp::max(x, 1) as usize);
+                        Update::Put(entry, expiration)
+                    });
+                    let result = format!("{}/{}\n", external_url, id);
+
+                    match updated {
+                        Ok(Ok(_)) => {
+                            Response::new()
+                                .with_status(StatusCode::Ok)
+                                .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
+                                .with_header(XContentTypeOptions(NOSNIFF.to_string()))
+                                .with_header(XRobotsTag(NOINDEX.to_string()))
+                                .with_body(result)
+                        }
+                        Ok(Err(refused)) => refused_response(refused),
+                        Err(_) => {
+                            metrics.backend_error();
+                            Response::new()
+                                .with_status(StatusCode::InternalServerError)
+                        }
+                    }
+                }))
+            }
             (Post, ref x) if RE_INV.is_match(x) => {
-                let id = self.gen_id().unwrap();
                 let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
                 let external_url = self.cfg.external_url.clone();
                 let expiration = self.get_expiration(&request);
+                let max_reads = match self.get_max_reads(&request) {
+                    Ok(max_reads) => max_reads,
+                    Err(_) => {
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::BadRequest)));
+                    }
+                };
+                let owner_token = self.new_owner_token(&request);
                 let max_value_size = self.cfg.max_value_size;
+                let cfg = self.cfg.clone();
+
+                let id_generator = match self.get_id_generator(&request) {
+                    Ok(id_generator) => id_generator,
+                    Err(err) => {
+                        debug!("rejecting request: {}", err);
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::BadRequest)));
+                    }
+                };
+
+                let redirect_status = match self.get_redirect_status(&request) {
+                    Ok(redirect_status) => redirect_status,
+                    Err(_) => {
+                        return Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::BadRequest)));
+                    }
+                };
 
                 if !request.headers().has::<QuiViveDstUrl>() {
                     Box::new(futures::future::ok(Response::new()
                         .with_status(StatusCode::BadRequest)))
                 } else {
                     let dst_url = request.headers().get::<QuiViveDstUrl>().unwrap().to_string();
+                    let content_type = Some(request_content_type(&request));
 
-                    let mut url = Url::parse(&dst_url).unwrap();
+                    let id_param = request.headers().get::<QuiViveIdParam>().map(|x| x.to_string());
 
-                    if let Some(id_param) = request.headers().get::<QuiViveIdParam>() {
-                        url.query_pairs_mut().append_pair(id_param.to_string().as_ref(), id.as_ref());
-                    }
+                    let dst_url = match destination::parse_destination(&self.cfg, &dst_url) {
+                        Ok(url) => url,
+                        Err(err) => {
+                            debug!("rejecting destination url: {}", err);
+                            return Box::new(futures::future::ok(Response::new()
+                                .with_status(StatusCode::BadRequest)));
+                        }
+                    };
 
                     Box::new(request.body().concat2().map(move |body| {
                         if body.len() > max_value_size {
-                            Response::new()
-                                .with_status(StatusCode::PayloadTooLarge)
-                        } else if let Ok(value) = String::from_utf8(body.to_vec()) {
-                            let entry = QuiViveEntry { id: id.clone(), val: value, url: url.to_string() };
-                            let result = format!("{}/{}\n", external_url, id);
+                            metrics.payload_too_large();
+                            return Response::new()
+                                .with_status(StatusCode::PayloadTooLarge);
+                        }
 
-                            if let Ok(_) = cache.insert_with(id.clone(), entry.clone(), expiration) {
-                                Response::new()
+                        let inserted = insert_new(&cache, &cfg, id_generator, expiration, |id| {
+                            let mut url = dst_url.clone();
+                            if let Some(ref id_param) = id_param {
+                                url.query_pairs_mut().append_pair(id_param, id.as_ref());
+                            }
+
+                            QuiViveEntry::new(id, KIND_INV, "".to_string(), url.to_string(), expiration)
+                                .with_content(body.to_vec(), content_type.clone())
+                                .with_max_reads(max_reads)
+                                .with_owner_token(owner_token.clone())
+                                .with_redirect_status(redirect_status)
+                        });
+
+                        match inserted {
+                            Ok(Some(entry)) => {
+                                metrics.created(Endpoint::Inv);
+                                let result = format!("{}/{}\n", external_url, entry.id);
+                                let response = Response::new()
                                     .with_status(StatusCode::Ok)
                                     .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
                                     .with_header(XContentTypeOptions(NOSNIFF.to_string()))
                                     .with_header(XRobotsTag(NOINDEX.to_string()))
-                                    .with_body(result)
-                            } else {
+                                    .with_body(result);
+                                with_owner_token(response, &entry)
+                            }
+                            Ok(None) => {
+                                warn!("failed to generate a free id after {} attempts", MAX_ID_ATTEMPTS);
+                                Response::new()
+                                    .with_status(StatusCode::InternalServerError)
+                            }
+                            Err(_) => {
+                                metrics.backend_error();
                                 Response::new()
                                     .with_status(StatusCode::InternalServerError)
                             }
-                        } else {
-                            Response::new()
-                                .with_status(StatusCode::BadRequest)
                         }
                     }))
                 }
@@ -338,34 +979,227 @@ impl Service for QuiViveService {
                 let id = cap[1].to_string();
 
                 let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
 
-                match cache.get::<String, QuiViveEntry>(id.clone()) {
+                match cache.get(id.clone()) {
                     Ok(Some(entry)) => {
+                        metrics.hit();
+                        metrics.redirect();
+                        self.record_click(&request, &entry);
+                        Box::new(futures::future::ok(self.redirect_response(&entry)))
+                    }
+                    Ok(_) => {
+                        metrics.miss();
                         Box::new(futures::future::ok(Response::new()
-                            .with_status(StatusCode::MovedPermanently)
-                            .with_header(Location::new(entry.url))
-                        ))
+                            .with_status(StatusCode::NotFound)))
                     }
-                    _ => {
+                    Err(_) => {
+                        metrics.backend_error();
+                        Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::NotFound)))
+                    }
+                }
+            }
+            (Put, ref x) if RE_INV_ID.is_match(x) => {
+                let cap = RE_INV_ID.captures(x).unwrap();
+                let id = cap[1].to_string();
+                let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
+                let external_url = self.cfg.external_url.clone();
+                let request_owner_token = request_owner_token(&request);
+                let max_value_size = self.cfg.max_value_size;
+                let content_type = if request.headers().has::<ContentType>() {
+                    Some(request_content_type(&request))
+                } else {
+                    None
+                };
+
+                // the destination url is optional, the payload is kept when it is not replaced
+                let url = match request.headers().get::<QuiViveDstUrl>() {
+                    Some(dst_url) => {
+                        match destination::parse_destination(&self.cfg, &dst_url.to_string()) {
+                            Ok(mut url) => {
+                                if let Some(id_param) = request.headers().get::<QuiViveIdParam>() {
+                                    url.query_pairs_mut().append_pair(id_param.to_string().as_ref(), id.as_ref());
+                                }
+                                Some(url.to_string())
+                            }
+                            Err(err) => {
+                                debug!("rejecting destination url: {}", err);
+                                return Box::new(futures::future::ok(Response::new()
+                                    .with_status(StatusCode::BadRequest)));
+                            }
+                        }
+                    }
+                    None => None,
+                };
+
+                Box::new(request.body().concat2().map(move |body| {
+                    if body.len() > max_value_size {
+                        metrics.payload_too_large();
+                        return Response::new()
+                            .with_status(StatusCode::PayloadTooLarge);
+                    }
+
+                    if url.is_none() && body.is_empty() {
+                        return Response::new()
+                            .with_status(StatusCode::BadRequest);
+                    }
+
+                    let updated = cache.update_owned(id.clone(), request_owner_token.as_ref().map(String::as_str), |entry| {
+                        if entry.kind != KIND_INV {
+                            return Update::Keep(StatusCode::NotFound);
+                        }
+
+                        // the id, expiration and stats are kept, only the destination and payload change
+                        let mut entry = entry.clone();
+                        if let Some(ref url) = url {
+                            entry.url = url.clone();
+                        }
+                        if !body.is_empty() {
+                            // without a Content-Type, the payload keeps its current one
+                            let content_type = content_type.clone().or_else(|| Some(entry.content_type.clone()));
+                            entry = entry.with_content(body.to_vec(), content_type);
+                        }
+                        let expiration = entry.ttl(get_timestamp()).map(|x| cmp::max(x, 1) as usize);
+                        Update::Put(entry, expiration)
+                    });
+                    let result = format!("{}/{}\n", external_url, id);
+
+                    match updated {
+                        Ok(Ok(_)) => {
+                            Response::new()
+                                .with_status(StatusCode::Ok)
+                                .with_header(ContentType(mime::TEXT_PLAIN_UTF_8))
+                                .with_header(XContentTypeOptions(NOSNIFF.to_string()))
+                                .with_header(XRobotsTag(NOINDEX.to_string()))
+                                .with_body(result)
+                        }
+                        Ok(Err(refused)) => refused_response(refused),
+                        Err(_) => {
+                            metrics.backend_error();
+                            Response::new()
+                                .with_status(StatusCode::InternalServerError)
+                        }
+                    }
+                }))
+            }
+            (Get, ref x) if RE_API_ENTRY_ID.is_match(x) => {
+                let cap = RE_API_ENTRY_ID.captures(x).unwrap();
+                let id = cap[1].to_string();
+
+                let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
+
+                match cache.get(id.clone()) {
+                    Ok(Some(entry)) => {
+                        metrics.hit();
+                        let info = entry_info(&entry, get_timestamp());
+                        Box::new(futures::future::ok(json_response(&info)))
+                    }
+                    Ok(_) => {
+                        metrics.miss();
+                        Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::NotFound)))
+                    }
+                    Err(_) => {
+                        metrics.backend_error();
+                        Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::NotFound)))
+                    }
+                }
+            }
+            (Get, ref x) if RE_API_ENTRY_STATS.is_match(x) => {
+                let cap = RE_API_ENTRY_STATS.captures(x).unwrap();
+                let id = cap[1].to_string();
+
+                let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
+
+                match (cache.get(id.clone()), cache.get_stats(id.clone())) {
+                    (Ok(_), Ok(Some(stats))) => {
+                        Box::new(futures::future::ok(json_response(&StatsInfo::new(&stats))))
+                    }
+                    (Ok(Some(_)), Ok(None)) => {
+                        // the entry exists but was never clicked
+                        let stats = QuiViveStats::new(id.clone());
+                        Box::new(futures::future::ok(json_response(&StatsInfo::new(&stats))))
+                    }
+                    (Ok(None), Ok(None)) => {
                         Box::new(futures::future::ok(Response::new()
                             .with_status(StatusCode::NotFound)))
                     }
+                    _ => {
+                        metrics.backend_error();
+                        Box::new(futures::future::ok(Response::new()
+                            .with_status(StatusCode::InternalServerError)))
+                    }
                 }
             }
+            (Put, ref x) if RE_API_ENTRY_TTL.is_match(x) => {
+                let cap = RE_API_ENTRY_TTL.captures(x).unwrap();
+                let id = cap[1].to_string();
+                let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
+                let request_owner_token = request_owner_token(&request);
+
+                Box::new(request.body().concat2().map(move|body| {
+                    // the new time to live, in seconds (0 means no expiration)
+                    let ttl = match String::from_utf8(body.to_vec()).ok().and_then(|x| x.trim().parse::<u32>().ok()) {
+                        Some(ttl) => ttl,
+                        None => {
+                            return Response::new()
+                                .with_status(StatusCode::BadRequest);
+                        }
+                    };
+
+                    let now = get_timestamp();
+                    let expiration = if ttl == 0 { None } else { Some(ttl as usize) };
+
+                    let updated = cache.update_owned(id.clone(), request_owner_token.as_ref().map(String::as_str), |entry| {
+                        let mut entry = entry.clone();
+                        entry.expires = expiration.map_or(0, |x| now + x as u32);
+                        Update::<StatusCode>::Put(entry, expiration)
+                    });
+
+                    // stats only exist once the entry was clicked, and expire along with it
+                    let updated = match updated {
+                        Ok(Ok(entry)) => cache.touch_stats(&id, expiration).map(|_| Ok(entry)),
+                        updated => updated,
+                    };
+
+                    match updated {
+                        Ok(Ok(entry)) => json_response(&entry_info(&entry, now)),
+                        Ok(Err(refused)) => refused_response(refused),
+                        Err(_) => {
+                            metrics.backend_error();
+                            Response::new()
+                                .with_status(StatusCode::InternalServerError)
+                        }
+                    }
+                }))
+            }
             (Get, ref x) if RE_ID.is_match(x) => {
                 let cap = RE_ID.captures(x).unwrap();
                 let id = cap[1].to_string();
 
                 let cache = self.cache.clone();
+                let metrics = self.metrics.clone();
 
-                match cache.get::<String, QuiViveEntry>(id.clone()) {
+                match cache.get(id.clone()) {
                     Ok(Some(ref entry)) if !entry.url.is_empty() => {
+                        metrics.hit();
+                        metrics.redirect();
+                        self.record_click(&request, entry);
+                        Box::new(futures::future::ok(self.redirect_response(entry)))
+                    }
+                    Ok(_) => {
+                        metrics.miss();
                         Box::new(futures::future::ok(Response::new()
-                            .with_status(StatusCode::MovedPermanently)
-                            .with_header(Location::new(entry.url.clone()))
-                        ))
+                            .with_status(StatusCode::NotFound)))
                     }
-                    _ => {
+                    Err(_) => {
+                        metrics.backend_error();
                         Box::new(futures::future::ok(Response::new()
                             .with_status(StatusCode::NotFound)))
                     }
@@ -378,3 +1212,54 @@ impl Service for QuiViveService {
         }
     }
 }
+
+impl Service for QuiViveService {
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item=Response<Body>, Error=hyper::Error>>;
+
+    fn call(&self, request: Request) -> Self::Future {
+        let start = Instant::now();
+        let method = request.method().clone();
+        let metrics = self.metrics.clone();
+
+        Box::new(self.route(request).then(move |result| {
+            metrics.observe_latency(&method, start.elapsed());
+            result
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_aliases() {
+        assert!(is_valid_alias("summit2026"));
+        assert!(is_valid_alias("Summit_2026-keynote"));
+        assert!(is_valid_alias("7"));
+        assert!(is_valid_alias(&"a".repeat(64)));
+        assert!(is_valid_alias("healthy"));
+    }
+
+    #[test]
+    fn rejects_invalid_aliases() {
+        assert!(!is_valid_alias(""));
+        assert!(!is_valid_alias("-summit"));
+        assert!(!is_valid_alias("_summit"));
+        assert!(!is_valid_alias("summit/2026"));
+        assert!(!is_valid_alias("summit.2026"));
+        assert!(!is_valid_alias("sümmit"));
+        assert!(!is_valid_alias(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn rejects_reserved_aliases() {
+        for reserved in RESERVED_ALIASES {
+            assert!(!is_valid_alias(reserved));
+            assert!(!is_valid_alias(&reserved.to_uppercase()));
+        }
+    }
+}
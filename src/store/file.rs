@@ -0,0 +1,320 @@
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use fs2::FileExt;
+use serde_json;
+
+use crate::service::get_timestamp;
+
+use super::{Store, StoreError, Ttl};
+
+#[derive(Serialize, Deserialize)]
+struct FileRecord {
+    expires: Option<u32>, // absolute timestamp, in seconds
+    value: String,
+}
+
+impl FileRecord {
+    fn new(value: &str, ttl: Option<u32>) -> Self {
+        FileRecord {
+            expires: ttl.map(|ttl| get_timestamp() + ttl),
+            value: value.to_string(),
+        }
+    }
+
+    fn is_expired(&self, now: u32) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+}
+
+// not a .json file, so never mistaken for a record
+const LOCK_FILE_NAME: &str = "qui-vive.lock";
+
+// expired records are otherwise only removed when their key is accessed again
+const PURGE_INTERVAL_WRITES: usize = 1000;
+
+/// Persists each key as a JSON document in the data directory.
+///
+/// Operations hold an advisory lock on the data directory, such that the server and admin
+/// subcommands can safely use the same directory at the same time.
+pub struct FileStore {
+    data_dir: PathBuf,
+    lock: Mutex<fs::File>, // file locks do not exclude threads sharing the file, hence the mutex
+    writes: AtomicUsize, // since the last purge
+}
+
+// releases the advisory lock before the mutex
+struct FileLock<'a> {
+    file: MutexGuard<'a, fs::File>,
+}
+
+impl<'a> Drop for FileLock<'a> {
+    fn drop(&mut self) {
+        if let Err(err) = self.file.unlock() {
+            warn!("failed to unlock the data directory: {}", err);
+        }
+    }
+}
+
+// keys can hold any character, so everything but [A-Za-z0-9_-] is percent-encoded in file names
+pub(super) fn encode_key(key: &str) -> String {
+    let mut name = String::new();
+    for b in key.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' => name.push(b as char),
+            _ => name.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    name
+}
+
+pub(super) fn decode_key(name: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut chars = name.bytes();
+    while let Some(b) = chars.next() {
+        if b == b'%' {
+            let hex = [chars.next()?, chars.next()?];
+            let hex = String::from_utf8(hex.to_vec()).ok()?;
+            bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn remove_file(path: &Path) -> Result<bool, StoreError> {
+    match fs::remove_file(path) {
+        Ok(_) => Ok(true),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(StoreError::from(err)),
+    }
+}
+
+impl FileStore {
+    pub fn new<P: AsRef<Path>>(data_dir: P) -> Result<Self, StoreError> {
+        let data_dir = data_dir.as_ref().to_path_buf();
+        fs::create_dir_all(&data_dir)?;
+
+        let lock_file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(data_dir.join(LOCK_FILE_NAME))?;
+
+        let store = FileStore {
+            data_dir: data_dir,
+            lock: Mutex::new(lock_file),
+            writes: AtomicUsize::new(0),
+        };
+
+        let purged = store.purge_expired()?;
+        info!("purged {} expired keys from {}", purged, store.data_dir.display());
+
+        Ok(store)
+    }
+
+    // waits for other threads and processes using the data directory
+    fn lock(&self) -> Result<FileLock, StoreError> {
+        let file = self.lock.lock().unwrap();
+        file.lock_exclusive()?;
+        Ok(FileLock { file: file })
+    }
+
+    fn record_path(&self, key: &str) -> PathBuf {
+        self.data_dir.join(format!("{}.json", encode_key(key)))
+    }
+
+    fn read_record(&self, path: &Path) -> Result<Option<FileRecord>, StoreError> {
+        let mut file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(StoreError::from(err)),
+        };
+
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+        Ok(Some(serde_json::from_str(&data)?))
+    }
+
+    // must be called with the store lock held, removes the record if it expired
+    fn read_live_record(&self, key: &str) -> Result<Option<FileRecord>, StoreError> {
+        let path = self.record_path(key);
+        match self.read_record(&path)? {
+            Some(ref record) if record.is_expired(get_timestamp()) => {
+                remove_file(&path)?;
+                Ok(None)
+            }
+            record => Ok(record),
+        }
+    }
+
+    // must be called with the store lock held
+    fn write_record(&self, key: &str, record: &FileRecord) -> Result<(), StoreError> {
+        let data = serde_json::to_string(record)?;
+        let path = self.record_path(key);
+        let tmp_path = self.data_dir.join(format!("{}.json.tmp", encode_key(key)));
+
+        // write to a temporary file first so that readers never observe a partial record
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(data.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+
+        if self.writes.fetch_add(1, Ordering::SeqCst) + 1 >= PURGE_INTERVAL_WRITES {
+            match self.purge_locked() {
+                Ok(purged) => debug!("purged {} expired keys from {}", purged, self.data_dir.display()),
+                Err(err) => warn!("failed to purge expired keys from {}: {}", self.data_dir.display(), err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the expired records, returning how many were removed. This runs when the store is opened
+    /// and then every 1000 writes.
+    pub fn purge_expired(&self) -> Result<usize, StoreError> {
+        let _guard = self.lock()?;
+        self.purge_locked()
+    }
+
+    // must be called with the store lock held
+    fn purge_locked(&self) -> Result<usize, StoreError> {
+        let now = get_timestamp();
+        let mut purged = 0;
+
+        self.writes.store(0, Ordering::SeqCst);
+
+        for dir_entry in fs::read_dir(&self.data_dir)? {
+            let path = dir_entry?.path();
+
+            if path.extension().map_or(true, |ext| ext != "json") {
+                continue;
+            }
+
+            match self.read_record(&path) {
+                Ok(Some(ref record)) if record.is_expired(now) => {
+                    remove_file(&path)?;
+                    purged += 1;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("skipping unreadable record {}: {}", path.display(), err);
+                }
+            }
+        }
+
+        Ok(purged)
+    }
+}
+
+impl Store for FileStore {
+    fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        let _guard = self.lock()?;
+        Ok(self.read_live_record(key)?.map(|record| record.value))
+    }
+
+    fn put(&self, key: &str, value: &str, ttl: Option<u32>) -> Result<(), StoreError> {
+        let _guard = self.lock()?;
+        self.write_record(key, &FileRecord::new(value, ttl))
+    }
+
+    fn put_if_absent(&self, key: &str, value: &str, ttl: Option<u32>) -> Result<bool, StoreError> {
+        self.compare_and_swap(key, None, value, ttl)
+    }
+
+    fn compare_and_swap(&self, key: &str, expected: Option<&str>, value: &str, ttl: Option<u32>) -> Result<bool, StoreError> {
+        let _guard = self.lock()?;
+        let current = self.read_live_record(key)?;
+
+        if current.as_ref().map(|record| record.value.as_str()) != expected {
+            return Ok(false);
+        }
+
+        self.write_record(key, &FileRecord::new(value, ttl))?;
+        Ok(true)
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, StoreError> {
+        let _guard = self.lock()?;
+        match self.read_live_record(key)? {
+            Some(_) => remove_file(&self.record_path(key)),
+            None => Ok(false),
+        }
+    }
+
+    fn compare_and_delete(&self, key: &str, expected: &str) -> Result<bool, StoreError> {
+        let _guard = self.lock()?;
+        match self.read_live_record(key)? {
+            Some(ref record) if record.value == expected => remove_file(&self.record_path(key)),
+            _ => Ok(false),
+        }
+    }
+
+    fn ttl(&self, key: &str) -> Result<Ttl, StoreError> {
+        let _guard = self.lock()?;
+        Ok(match self.read_live_record(key)? {
+            None => Ttl::Missing,
+            Some(FileRecord { expires: None, .. }) => Ttl::Persistent,
+            Some(FileRecord { expires: Some(expires), .. }) => Ttl::Expires(expires.saturating_sub(get_timestamp())),
+        })
+    }
+
+    fn touch(&self, key: &str, ttl: Option<u32>) -> Result<bool, StoreError> {
+        let _guard = self.lock()?;
+        match self.read_live_record(key)? {
+            Some(record) => {
+                self.write_record(key, &FileRecord::new(&record.value, ttl))?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        let now = get_timestamp();
+        let mut keys = Vec::new();
+
+        let _guard = self.lock()?;
+
+        for dir_entry in fs::read_dir(&self.data_dir)? {
+            let path = dir_entry?.path();
+
+            let key = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if name.ends_with(".json") => decode_key(&name[..name.len() - 5]),
+                _ => None,
+            };
+
+            match key {
+                Some(ref key) if key.starts_with(prefix) => {}
+                _ => continue,
+            }
+
+            match self.read_record(&path) {
+                Ok(Some(ref record)) if !record.is_expired(now) => keys.push(key.unwrap()),
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("skipping unreadable record {}: {}", path.display(), err);
+                }
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn incr(&self, key: &str, delta: i64) -> Result<i64, StoreError> {
+        let _guard = self.lock()?;
+
+        let current = match self.read_live_record(key)? {
+            Some(record) => record.value.parse::<i64>()
+                .map_err(|_| StoreError::Data(format!("{} is not an integer", key)))?,
+            None => 0,
+        };
+
+        let value = current + delta;
+        self.write_record(key, &FileRecord::new(&value.to_string(), None))?;
+        Ok(value)
+    }
+}
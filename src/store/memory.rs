@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{Store, StoreError, Ttl};
+
+struct MemoryValue {
+    value: String,
+    expires: Option<Instant>,
+}
+
+impl MemoryValue {
+    fn new(value: &str, ttl: Option<u32>) -> Self {
+        MemoryValue {
+            value: value.to_string(),
+            expires: ttl.map(|ttl| Instant::now() + Duration::from_secs(ttl as u64)),
+        }
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+}
+
+/// Keeps all keys in process memory, such that they are lost on restart.
+#[derive(Clone)]
+pub struct MemoryStore {
+    values: Arc<Mutex<HashMap<String, MemoryValue>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore {
+            values: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+// removes the key if it expired, such that the map only holds live values for it
+fn purge_expired(values: &mut HashMap<String, MemoryValue>, key: &str, now: Instant) {
+    if values.get(key).map_or(false, |value| value.is_expired(now)) {
+        values.remove(key);
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        let mut values = self.values.lock().unwrap();
+        purge_expired(&mut values, key, Instant::now());
+        Ok(values.get(key).map(|value| value.value.clone()))
+    }
+
+    fn put(&self, key: &str, value: &str, ttl: Option<u32>) -> Result<(), StoreError> {
+        let mut values = self.values.lock().unwrap();
+        values.insert(key.to_string(), MemoryValue::new(value, ttl));
+        Ok(())
+    }
+
+    fn put_if_absent(&self, key: &str, value: &str, ttl: Option<u32>) -> Result<bool, StoreError> {
+        self.compare_and_swap(key, None, value, ttl)
+    }
+
+    fn compare_and_swap(&self, key: &str, expected: Option<&str>, value: &str, ttl: Option<u32>) -> Result<bool, StoreError> {
+        let mut values = self.values.lock().unwrap();
+        purge_expired(&mut values, key, Instant::now());
+
+        if values.get(key).map(|current| current.value.as_str()) != expected {
+            return Ok(false);
+        }
+
+        values.insert(key.to_string(), MemoryValue::new(value, ttl));
+        Ok(true)
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, StoreError> {
+        let mut values = self.values.lock().unwrap();
+        purge_expired(&mut values, key, Instant::now());
+        Ok(values.remove(key).is_some())
+    }
+
+    fn compare_and_delete(&self, key: &str, expected: &str) -> Result<bool, StoreError> {
+        let mut values = self.values.lock().unwrap();
+        purge_expired(&mut values, key, Instant::now());
+
+        if values.get(key).map(|current| current.value.as_str()) != Some(expected) {
+            return Ok(false);
+        }
+
+        values.remove(key);
+        Ok(true)
+    }
+
+    fn ttl(&self, key: &str) -> Result<Ttl, StoreError> {
+        let now = Instant::now();
+        let mut values = self.values.lock().unwrap();
+        purge_expired(&mut values, key, now);
+
+        Ok(match values.get(key) {
+            None => Ttl::Missing,
+            Some(&MemoryValue { expires: None, .. }) => Ttl::Persistent,
+            Some(&MemoryValue { expires: Some(expires), .. }) => {
+                // rounded up, such that a live key never reports a ttl of 0
+                let remaining = expires.duration_since(now);
+                let secs = remaining.as_secs() + if remaining.subsec_nanos() > 0 { 1 } else { 0 };
+                Ttl::Expires(secs as u32)
+            }
+        })
+    }
+
+    fn touch(&self, key: &str, ttl: Option<u32>) -> Result<bool, StoreError> {
+        let mut values = self.values.lock().unwrap();
+        purge_expired(&mut values, key, Instant::now());
+
+        match values.get_mut(key) {
+            Some(value) => {
+                value.expires = ttl.map(|ttl| Instant::now() + Duration::from_secs(ttl as u64));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        let now = Instant::now();
+        let mut values = self.values.lock().unwrap();
+        values.retain(|_, value| !value.is_expired(now));
+        Ok(values.keys().filter(|key| key.starts_with(prefix)).cloned().collect())
+    }
+
+    fn incr(&self, key: &str, delta: i64) -> Result<i64, StoreError> {
+        let mut values = self.values.lock().unwrap();
+        purge_expired(&mut values, key, Instant::now());
+
+        let current = match values.get(key) {
+            Some(value) => value.value.parse::<i64>()
+                .map_err(|_| StoreError::Data(format!("{} is not an integer", key)))?,
+            None => 0,
+        };
+
+        let value = current + delta;
+        values.insert(key.to_string(), MemoryValue::new(&value.to_string(), None));
+        Ok(value)
+    }
+}
@@ -0,0 +1,109 @@
+//! Storage backends, behind a small key-value interface with atomic operations.
+//!
+//! Values are opaque strings and expirations are in seconds. The memory, redis and file
+//! backends implement `Store`, and other backends can be plugged in by implementing it too.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use serde_json;
+
+mod memory;
+mod redis;
+mod file;
+
+#[cfg(test)]
+mod tests;
+
+pub use self::memory::MemoryStore;
+pub use self::redis::RedisStore;
+pub use self::file::FileStore;
+
+#[derive(Debug)]
+pub enum StoreError {
+    Connection(String), // the backend could not be reached
+    Data(String), // a stored value could not be read or written
+    Other(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StoreError::Connection(ref msg) => write!(f, "connection error: {}", msg),
+            StoreError::Data(ref msg) => write!(f, "data error: {}", msg),
+            StoreError::Other(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for StoreError {
+    fn description(&self) -> &str {
+        match *self {
+            StoreError::Connection(ref msg) => msg,
+            StoreError::Data(ref msg) => msg,
+            StoreError::Other(ref msg) => msg,
+        }
+    }
+}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> Self {
+        StoreError::Data(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(err: serde_json::Error) -> Self {
+        StoreError::Data(err.to_string())
+    }
+}
+
+/// Remaining lifetime of a key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Ttl {
+    Missing, // the key does not exist
+    Persistent, // the key never expires
+    Expires(u32), // the key expires in the given number of seconds
+}
+
+/// Key-value store with the atomic operations needed by qui-vive.
+///
+/// A `ttl` of None means the key never expires. Expired keys must behave exactly like missing keys.
+pub trait Store: Send + Sync {
+    /// Gets the value of a key, with None if it does not exist.
+    fn get(&self, key: &str) -> Result<Option<String>, StoreError>;
+
+    /// Sets the value of a key, replacing any existing value.
+    fn put(&self, key: &str, value: &str, ttl: Option<u32>) -> Result<(), StoreError>;
+
+    /// Sets the value of a key only if it does not exist, returning false otherwise.
+    fn put_if_absent(&self, key: &str, value: &str, ttl: Option<u32>) -> Result<bool, StoreError>;
+
+    /// Sets the value of a key only if its current value is `expected` (None meaning that the key
+    /// does not exist), returning false otherwise.
+    fn compare_and_swap(&self, key: &str, expected: Option<&str>, value: &str, ttl: Option<u32>) -> Result<bool, StoreError>;
+
+    /// Deletes a key, returning false if it did not exist.
+    fn delete(&self, key: &str) -> Result<bool, StoreError>;
+
+    /// Deletes a key only if its current value is `expected`, returning false otherwise.
+    fn compare_and_delete(&self, key: &str, expected: &str) -> Result<bool, StoreError>;
+
+    fn ttl(&self, key: &str) -> Result<Ttl, StoreError>;
+
+    /// Changes the expiration of a key without changing its value, returning false if it does not exist.
+    fn touch(&self, key: &str, ttl: Option<u32>) -> Result<bool, StoreError>;
+
+    /// Lists the keys starting with the given prefix, in no particular order.
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<String>, StoreError>;
+
+    /// Adds `delta` to an integer value that never expires, returning the new value (missing keys start at 0).
+    fn incr(&self, key: &str, delta: i64) -> Result<i64, StoreError>;
+
+    /// Calls the listener with the keys changed by other processes sharing the store, if any.
+    /// Stores that are not shared between processes have nothing to report.
+    fn listen(&self, _listener: Box<Fn(&str) + Send + Sync>) -> Result<(), StoreError> {
+        Ok(())
+    }
+}
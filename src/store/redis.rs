@@ -0,0 +1,342 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use redis;
+use redis::{Commands, Connection, ConnectionAddr, ConnectionInfo, RedisError, RedisResult, Script};
+
+use super::{Store, StoreError, Ttl};
+
+// keeps qui-vive keys apart from other data, including the hashes written by older versions
+const KEY_PREFIX: &str = "qui-vive:";
+
+const DEFAULT_PORT: u16 = 6379;
+
+// written keys are published on this channel, such that all instances can wake up their waiting requests
+const CHANGES_CHANNEL: &str = "qui-vive:changes";
+
+const RECONNECT_DELAY_SECS: u64 = 1;
+
+// idle connections kept for reuse, more are opened while requests run concurrently
+const MAX_IDLE_CONNECTIONS: usize = 16;
+
+// SET only supports comparing with a missing key (NX) and DEL none at all, so other comparisons need
+// a script, which deletes the key instead of setting it when ARGV[5] is '1'
+const COMPARE_AND_SWAP_SCRIPT: &str = r"
+local current = redis.call('GET', KEYS[1])
+if ARGV[1] == '1' then
+    if current ~= ARGV[2] then return 0 end
+elseif current then
+    return 0
+end
+if ARGV[5] == '1' then
+    redis.call('DEL', KEYS[1])
+elseif ARGV[4] == '0' then
+    redis.call('SET', KEYS[1], ARGV[3])
+else
+    redis.call('SET', KEYS[1], ARGV[3], 'EX', ARGV[4])
+end
+return 1
+";
+
+lazy_static! {
+    static ref COMPARE_AND_SWAP: Script = Script::new(COMPARE_AND_SWAP_SCRIPT);
+}
+
+impl From<RedisError> for StoreError {
+    fn from(err: RedisError) -> Self {
+        if err.is_io_error() || err.is_connection_dropped() || err.is_connection_refusal() {
+            StoreError::Connection(err.to_string())
+        } else {
+            StoreError::Other(err.to_string())
+        }
+    }
+}
+
+/// Stores keys in a redis server, which can be shared by several qui-vive instances.
+pub struct RedisStore {
+    client: redis::Client,
+    idle_connections: Mutex<Vec<Connection>>,
+}
+
+impl RedisStore {
+    /// Connects to a redis server given as 'hostname' or 'hostname:port'.
+    pub fn open(hostname: &str, password: Option<&str>) -> Result<Self, StoreError> {
+        let (host, port) = match hostname.rfind(':') {
+            Some(index) => {
+                let port = hostname[index + 1..].parse::<u16>()
+                    .map_err(|_| StoreError::Other(format!("invalid redis hostname: {}", hostname)))?;
+                (&hostname[..index], port)
+            }
+            None => (hostname, DEFAULT_PORT),
+        };
+
+        let client = redis::Client::open(ConnectionInfo {
+            addr: Box::new(ConnectionAddr::Tcp(host.to_string(), port)),
+            db: 0,
+            passwd: password.map(|x| x.to_string()),
+        })?;
+
+        // connecting right away reports an unreachable server at startup
+        let connection = client.get_connection()?;
+
+        Ok(RedisStore {
+            client: client,
+            idle_connections: Mutex::new(vec![connection]),
+        })
+    }
+
+    // runs a command on an idle connection, or on a new one if they are all in use
+    fn with_connection<T, F>(&self, f: F) -> Result<T, StoreError>
+        where F: FnOnce(&mut Connection) -> RedisResult<T>
+    {
+        // the lock is only held to take and give back connections, such that commands run concurrently
+        let idle_connection = self.idle_connections.lock().unwrap().pop();
+        let mut connection = match idle_connection {
+            Some(connection) => connection,
+            None => self.client.get_connection()?,
+        };
+
+        let result = f(&mut connection);
+
+        // dropped connections are not reused, the next command opens a new one
+        let dropped = match result {
+            Err(ref err) => err.is_io_error() || err.is_connection_dropped(),
+            Ok(_) => false,
+        };
+
+        if !dropped {
+            let mut idle_connections = self.idle_connections.lock().unwrap();
+            if idle_connections.len() < MAX_IDLE_CONNECTIONS {
+                idle_connections.push(connection);
+            }
+        }
+
+        result.map_err(StoreError::from)
+    }
+
+    /// Copies the hashes written by older versions as `<old_prefix>:<id>` to `<new_prefix><id>`, keeping their
+    /// time to live, with values converted from the hash fields. Returns the number of copied hashes.
+    /// The hashes are only deleted with `delete_old`, since older instances may still be serving them.
+    pub fn migrate_hashes<F>(&self, old_prefix: &str, new_prefix: &str, convert: F, delete_old: bool) -> Result<usize, StoreError>
+        where F: Fn(&HashMap<String, String>, Option<u32>) -> Result<String, StoreError>
+    {
+        let pattern = format!("{}:*", escape_pattern(old_prefix));
+        let mut old_keys: Vec<String> = self.with_connection(|con| {
+            Ok(con.scan_match::<_, String>(pattern)?.collect())
+        })?;
+        old_keys.sort();
+        old_keys.dedup();
+
+        let mut migrated = 0;
+
+        for old_key in old_keys {
+            let fields: HashMap<String, String> = match self.with_connection(|con| con.hgetall(&old_key)) {
+                Ok(fields) => fields,
+                Err(StoreError::Connection(err)) => return Err(StoreError::Connection(err)),
+                Err(err) => {
+                    warn!("skipping {}: {}", old_key, err);
+                    continue;
+                }
+            };
+
+            // another migration may have deleted it meanwhile
+            if fields.is_empty() {
+                continue;
+            }
+
+            let ttl: i64 = self.with_connection(|con| con.ttl(&old_key))?;
+            let ttl = if ttl > 0 { Some(ttl as u32) } else { None };
+
+            let value = match convert(&fields, ttl) {
+                Ok(value) => value,
+                Err(err) => {
+                    warn!("skipping {}: {}", old_key, err);
+                    continue;
+                }
+            };
+
+            // entries written since the upgrade, or copied by an earlier migration, take precedence
+            let new_key = format!("{}{}", new_prefix, &old_key[old_prefix.len() + 1..]);
+            if self.put_if_absent(&new_key, &value, ttl)? {
+                migrated += 1;
+            }
+
+            if delete_old {
+                let _: i32 = self.with_connection(|con| con.del(&old_key))?;
+            }
+        }
+
+        Ok(migrated)
+    }
+
+    // a failed notification only delays waiting requests until their next deadline, so the write still succeeds
+    fn publish(&self, key: &str) {
+        let result: Result<i64, StoreError> = self.with_connection(|con| con.publish(CHANGES_CHANNEL, key));
+        if let Err(err) = result {
+            warn!("failed to publish change of {}: {}", key, err);
+        }
+    }
+}
+
+// receives the published changes until the connection fails
+fn receive_changes(client: &redis::Client, listener: &Fn(&str)) -> RedisResult<()> {
+    let mut connection = client.get_connection()?;
+    let mut pubsub = connection.as_pubsub();
+    pubsub.subscribe(CHANGES_CHANNEL)?;
+
+    loop {
+        let message = pubsub.get_message()?;
+        let key: String = message.get_payload()?;
+        listener(&key);
+    }
+}
+
+fn redis_key(key: &str) -> String {
+    format!("{}{}", KEY_PREFIX, key)
+}
+
+// SCAN patterns are globs, so the prefix must not be interpreted as one
+fn escape_pattern(prefix: &str) -> String {
+    let mut pattern = String::new();
+    for c in prefix.chars() {
+        if c == '*' || c == '?' || c == '[' || c == ']' || c == '\\' {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+fn set_command(key: &str, value: &str, ttl: Option<u32>) -> redis::Cmd {
+    let mut command = redis::cmd("SET");
+    command.arg(redis_key(key)).arg(value);
+    if let Some(ttl) = ttl {
+        // redis rejects an expiration of 0 seconds
+        command.arg("EX").arg(cmp::max(ttl, 1));
+    }
+    command
+}
+
+impl Store for RedisStore {
+    fn get(&self, key: &str) -> Result<Option<String>, StoreError> {
+        self.with_connection(|con| con.get(redis_key(key)))
+    }
+
+    fn put(&self, key: &str, value: &str, ttl: Option<u32>) -> Result<(), StoreError> {
+        self.with_connection(|con| set_command(key, value, ttl).query(con))?;
+        self.publish(key);
+        Ok(())
+    }
+
+    fn put_if_absent(&self, key: &str, value: &str, ttl: Option<u32>) -> Result<bool, StoreError> {
+        let mut command = set_command(key, value, ttl);
+        command.arg("NX");
+        let reply: Option<String> = self.with_connection(|con| command.query(con))?;
+        if reply.is_some() {
+            self.publish(key);
+        }
+        Ok(reply.is_some())
+    }
+
+    fn compare_and_swap(&self, key: &str, expected: Option<&str>, value: &str, ttl: Option<u32>) -> Result<bool, StoreError> {
+        let swapped: i32 = self.with_connection(|con| {
+            COMPARE_AND_SWAP.key(redis_key(key))
+                .arg(if expected.is_some() { "1" } else { "0" })
+                .arg(expected.unwrap_or(""))
+                .arg(value)
+                .arg(ttl.map_or(0, |ttl| cmp::max(ttl, 1)))
+                .arg("0")
+                .invoke(con)
+        })?;
+        if swapped == 1 {
+            self.publish(key);
+        }
+        Ok(swapped == 1)
+    }
+
+    fn compare_and_delete(&self, key: &str, expected: &str) -> Result<bool, StoreError> {
+        let deleted: i32 = self.with_connection(|con| {
+            COMPARE_AND_SWAP.key(redis_key(key))
+                .arg("1")
+                .arg(expected)
+                .arg("")
+                .arg(0)
+                .arg("1")
+                .invoke(con)
+        })?;
+        if deleted == 1 {
+            self.publish(key);
+        }
+        Ok(deleted == 1)
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, StoreError> {
+        let deleted: i32 = self.with_connection(|con| con.del(redis_key(key)))?;
+        if deleted > 0 {
+            self.publish(key);
+        }
+        Ok(deleted > 0)
+    }
+
+    fn ttl(&self, key: &str) -> Result<Ttl, StoreError> {
+        let ttl: i64 = self.with_connection(|con| con.ttl(redis_key(key)))?;
+        Ok(match ttl {
+            -2 => Ttl::Missing,
+            -1 => Ttl::Persistent,
+            ttl => Ttl::Expires(ttl as u32),
+        })
+    }
+
+    fn touch(&self, key: &str, ttl: Option<u32>) -> Result<bool, StoreError> {
+        let redis_key = redis_key(key);
+        let touched = self.with_connection(|con| {
+            match ttl {
+                Some(ttl) => con.expire(redis_key, cmp::max(ttl, 1) as usize),
+                // PERSIST also returns 0 for existing keys without an expiration
+                None => {
+                    let persisted: bool = con.persist(redis_key.clone())?;
+                    if persisted { Ok(true) } else { con.exists(redis_key) }
+                }
+            }
+        })?;
+        if touched {
+            self.publish(key);
+        }
+        Ok(touched)
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        let pattern = format!("{}{}*", KEY_PREFIX, escape_pattern(prefix));
+        let mut keys: Vec<String> = self.with_connection(|con| {
+            Ok(con.scan_match::<_, String>(pattern)?.collect())
+        })?;
+
+        // SCAN can return the same key more than once
+        keys.sort();
+        keys.dedup();
+        Ok(keys.into_iter().map(|key| key[KEY_PREFIX.len()..].to_string()).collect())
+    }
+
+    fn incr(&self, key: &str, delta: i64) -> Result<i64, StoreError> {
+        self.with_connection(|con| con.incr(redis_key(key), delta))
+    }
+
+    fn listen(&self, listener: Box<Fn(&str) + Send + Sync>) -> Result<(), StoreError> {
+        let client = self.client.clone();
+
+        // subscribed connections cannot run other commands, so changes are received on their own thread
+        thread::spawn(move || {
+            loop {
+                if let Err(err) = receive_changes(&client, &*listener) {
+                    warn!("failed to receive changes from redis: {}", err);
+                }
+                thread::sleep(Duration::from_secs(RECONNECT_DELAY_SECS));
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+//! Conformance tests run against every `Store` implementation. The redis store is only tested
+//! when `REDIS_HOSTNAME` (and optionally `REDIS_PASSWORD`) point to a server.
+
+use std::env;
+use std::fs;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use uuid::Uuid;
+
+use super::{FileStore, MemoryStore, RedisStore, Store, Ttl};
+use super::file::{decode_key, encode_key};
+
+fn assert_expires(ttl: Ttl, expected: u32) {
+    match ttl {
+        // stores round the remaining time differently, and a second may have passed
+        Ttl::Expires(ttl) => assert!(ttl + 2 >= expected && ttl <= expected, "unexpected ttl {}", ttl),
+        ttl => panic!("expected an expiration, got {:?}", ttl),
+    }
+}
+
+fn check_put_get(store: &Store, prefix: &str) {
+    let key = format!("{}put", prefix);
+
+    assert_eq!(store.get(&key).unwrap(), None);
+    store.put(&key, "one", None).unwrap();
+    assert_eq!(store.get(&key).unwrap(), Some("one".to_string()));
+    store.put(&key, "two", None).unwrap();
+    assert_eq!(store.get(&key).unwrap(), Some("two".to_string()));
+}
+
+fn check_put_if_absent(store: &Store, prefix: &str) {
+    let key = format!("{}absent", prefix);
+
+    assert!(store.put_if_absent(&key, "one", None).unwrap());
+    assert!(!store.put_if_absent(&key, "two", None).unwrap());
+    assert_eq!(store.get(&key).unwrap(), Some("one".to_string()));
+
+    let key = format!("{}absent-ttl", prefix);
+    assert!(store.put_if_absent(&key, "one", Some(60)).unwrap());
+    assert_expires(store.ttl(&key).unwrap(), 60);
+}
+
+fn check_compare_and_swap(store: &Store, prefix: &str) {
+    let key = format!("{}cas", prefix);
+
+    assert!(store.compare_and_swap(&key, None, "one", None).unwrap());
+    assert!(!store.compare_and_swap(&key, None, "two", None).unwrap());
+    assert!(!store.compare_and_swap(&key, Some("two"), "three", None).unwrap());
+    assert_eq!(store.get(&key).unwrap(), Some("one".to_string()));
+
+    assert!(store.compare_and_swap(&key, Some("one"), "two", Some(60)).unwrap());
+    assert_eq!(store.get(&key).unwrap(), Some("two".to_string()));
+    assert_expires(store.ttl(&key).unwrap(), 60);
+
+    // swapping without a ttl removes the expiration
+    assert!(store.compare_and_swap(&key, Some("two"), "three", None).unwrap());
+    assert_eq!(store.ttl(&key).unwrap(), Ttl::Persistent);
+
+    // an empty value is not the same as a missing key
+    let key = format!("{}cas-empty", prefix);
+    assert!(!store.compare_and_swap(&key, Some(""), "one", None).unwrap());
+    assert!(store.compare_and_swap(&key, None, "", None).unwrap());
+    assert!(!store.compare_and_swap(&key, None, "one", None).unwrap());
+    assert!(store.compare_and_swap(&key, Some(""), "one", None).unwrap());
+}
+
+fn check_delete(store: &Store, prefix: &str) {
+    let key = format!("{}delete", prefix);
+
+    assert!(!store.delete(&key).unwrap());
+    store.put(&key, "one", None).unwrap();
+    assert!(store.delete(&key).unwrap());
+    assert!(!store.delete(&key).unwrap());
+    assert_eq!(store.get(&key).unwrap(), None);
+}
+
+fn check_compare_and_delete(store: &Store, prefix: &str) {
+    let key = format!("{}cad", prefix);
+
+    assert!(!store.compare_and_delete(&key, "one").unwrap());
+    store.put(&key, "one", Some(60)).unwrap();
+    assert!(!store.compare_and_delete(&key, "two").unwrap());
+    assert!(!store.compare_and_delete(&key, "").unwrap());
+    assert_eq!(store.get(&key).unwrap(), Some("one".to_string()));
+
+    assert!(store.compare_and_delete(&key, "one").unwrap());
+    assert_eq!(store.get(&key).unwrap(), None);
+    assert!(!store.compare_and_delete(&key, "one").unwrap());
+}
+
+fn check_ttl_and_touch(store: &Store, prefix: &str) {
+    let key = format!("{}ttl", prefix);
+
+    assert_eq!(store.ttl(&key).unwrap(), Ttl::Missing);
+    assert!(!store.touch(&key, Some(60)).unwrap());
+    assert_eq!(store.get(&key).unwrap(), None);
+
+    store.put(&key, "one", None).unwrap();
+    assert_eq!(store.ttl(&key).unwrap(), Ttl::Persistent);
+
+    assert!(store.touch(&key, Some(60)).unwrap());
+    assert_expires(store.ttl(&key).unwrap(), 60);
+    assert_eq!(store.get(&key).unwrap(), Some("one".to_string()));
+
+    assert!(store.touch(&key, None).unwrap());
+    assert_eq!(store.ttl(&key).unwrap(), Ttl::Persistent);
+
+    // touching a key without expiration still reports it as existing
+    assert!(store.touch(&key, None).unwrap());
+    assert_eq!(store.get(&key).unwrap(), Some("one".to_string()));
+}
+
+fn check_incr(store: &Store, prefix: &str) {
+    let key = format!("{}counter", prefix);
+
+    assert_eq!(store.incr(&key, 1).unwrap(), 1);
+    assert_eq!(store.incr(&key, 2).unwrap(), 3);
+    assert_eq!(store.incr(&key, -1).unwrap(), 2);
+    assert_eq!(store.get(&key).unwrap(), Some("2".to_string()));
+    assert_eq!(store.ttl(&key).unwrap(), Ttl::Persistent);
+
+    let key = format!("{}not-a-counter", prefix);
+    store.put(&key, "one", None).unwrap();
+    assert!(store.incr(&key, 1).is_err());
+}
+
+fn check_scan_prefix(store: &Store, prefix: &str) {
+    let scan_prefix = format!("{}scan:", prefix);
+
+    store.put(&format!("{}a", scan_prefix), "a", None).unwrap();
+    store.put(&format!("{}b", scan_prefix), "b", Some(60)).unwrap();
+    store.put(&format!("{}scan-other", prefix), "c", None).unwrap();
+
+    let mut keys = store.scan_prefix(&scan_prefix).unwrap();
+    keys.sort();
+    assert_eq!(keys, vec![format!("{}a", scan_prefix), format!("{}b", scan_prefix)]);
+
+    // glob and path characters are matched literally
+    assert!(store.scan_prefix(&format!("{}sc*", prefix)).unwrap().is_empty());
+    assert!(store.scan_prefix(&format!("{}scan:?", prefix)).unwrap().is_empty());
+}
+
+fn check_special_keys(store: &Store, prefix: &str) {
+    let keys = ["a/b", "a\\b", "a:b*c?[d]", "100% é", "..", "a.json"];
+
+    for name in keys.iter() {
+        let key = format!("{}special:{}", prefix, name);
+        store.put(&key, name, None).unwrap();
+        assert_eq!(store.get(&key).unwrap(), Some(name.to_string()));
+    }
+
+    let mut found = store.scan_prefix(&format!("{}special:", prefix)).unwrap();
+    found.sort();
+    let mut expected: Vec<String> = keys.iter().map(|name| format!("{}special:{}", prefix, name)).collect();
+    expected.sort();
+    assert_eq!(found, expected);
+}
+
+// expired keys must behave exactly like missing keys, for every operation
+fn check_expired_is_missing(store: &Store, prefix: &str) {
+    let key = format!("{}expired", prefix);
+    let cas_key = format!("{}expired-cas", prefix);
+    let scan_key = format!("{}expired-scan:a", prefix);
+
+    store.put(&key, "one", Some(1)).unwrap();
+    store.put(&cas_key, "one", Some(1)).unwrap();
+    store.put(&scan_key, "one", Some(1)).unwrap();
+    thread::sleep(Duration::from_millis(2100));
+
+    assert_eq!(store.get(&key).unwrap(), None);
+    assert_eq!(store.ttl(&key).unwrap(), Ttl::Missing);
+    assert!(!store.touch(&key, None).unwrap());
+    assert!(!store.delete(&key).unwrap());
+    assert!(!store.compare_and_delete(&key, "one").unwrap());
+    assert!(store.scan_prefix(&format!("{}expired-scan:", prefix)).unwrap().is_empty());
+
+    assert!(!store.compare_and_swap(&cas_key, Some("one"), "two", None).unwrap());
+    assert!(store.compare_and_swap(&cas_key, None, "two", None).unwrap());
+
+    assert!(store.put_if_absent(&scan_key, "two", None).unwrap());
+    assert_eq!(store.get(&scan_key).unwrap(), Some("two".to_string()));
+}
+
+fn check_store(store: &Store, prefix: &str) {
+    check_put_get(store, prefix);
+    check_put_if_absent(store, prefix);
+    check_compare_and_swap(store, prefix);
+    check_delete(store, prefix);
+    check_compare_and_delete(store, prefix);
+    check_ttl_and_touch(store, prefix);
+    check_incr(store, prefix);
+    check_scan_prefix(store, prefix);
+    check_special_keys(store, prefix);
+    check_expired_is_missing(store, prefix);
+}
+
+#[test]
+fn memory_store() {
+    check_store(&MemoryStore::new(), "test:");
+}
+
+#[test]
+fn file_store() {
+    let data_dir = env::temp_dir().join(format!("qui-vive-test-{}", Uuid::new_v4()));
+    let store = FileStore::new(&data_dir).unwrap();
+
+    check_store(&store, "test:");
+
+    // reopening the store keeps live keys
+    drop(store);
+    let store = FileStore::new(&data_dir).unwrap();
+    assert_eq!(store.get("test:put").unwrap(), Some("two".to_string()));
+
+    fs::remove_dir_all(&data_dir).unwrap();
+}
+
+#[test]
+fn file_store_purges_expired_records() {
+    let data_dir = env::temp_dir().join(format!("qui-vive-test-{}", Uuid::new_v4()));
+    let store = FileStore::new(&data_dir).unwrap();
+
+    for i in 0..5 {
+        store.put(&format!("test:expired:{}", i), "one", Some(1)).unwrap();
+    }
+    thread::sleep(Duration::from_millis(2100));
+
+    // expired records that are never accessed again are removed after enough writes
+    for _ in 0..1000 {
+        store.put("test:other", "two", None).unwrap();
+    }
+
+    let records = fs::read_dir(&data_dir).unwrap()
+        .filter(|dir_entry| dir_entry.as_ref().unwrap().path().extension().map_or(false, |ext| ext == "json"))
+        .count();
+    assert_eq!(records, 1);
+
+    fs::remove_dir_all(&data_dir).unwrap();
+}
+
+#[test]
+fn file_store_shared_data_dir() {
+    let data_dir = env::temp_dir().join(format!("qui-vive-test-{}", Uuid::new_v4()));
+
+    // separate stores on the same directory stand for the server and an admin subcommand
+    let stores: Vec<Arc<FileStore>> = (0..2).map(|_| Arc::new(FileStore::new(&data_dir).unwrap())).collect();
+
+    let threads: Vec<_> = (0..4).map(|i| {
+        let store = stores[i % 2].clone();
+        thread::spawn(move || {
+            for _ in 0..50 {
+                store.incr("test:counter", 1).unwrap();
+            }
+        })
+    }).collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(stores[0].get("test:counter").unwrap(), Some("200".to_string()));
+    assert_eq!(stores[1].scan_prefix("").unwrap(), vec!["test:counter".to_string()]);
+
+    fs::remove_dir_all(&data_dir).unwrap();
+}
+
+#[test]
+fn redis_store() {
+    let hostname = match env::var("REDIS_HOSTNAME") {
+        Ok(hostname) => hostname,
+        Err(_) => return,
+    };
+    let password = env::var("REDIS_PASSWORD").ok();
+    let store = RedisStore::open(&hostname, password.as_ref().map(String::as_str)).unwrap();
+
+    // a unique prefix keeps concurrent test runs and other data apart
+    let prefix = format!("test-{}:", Uuid::new_v4());
+    check_store(&store, &prefix);
+
+    for key in store.scan_prefix(&prefix).unwrap() {
+        store.delete(&key).unwrap();
+    }
+}
+
+#[test]
+fn file_key_encoding() {
+    assert_eq!(encode_key("entry:Ab9_-"), "entry%3AAb9_-");
+    assert_eq!(encode_key("a/b.json"), "a%2Fb%2Ejson");
+    assert_eq!(encode_key("é"), "%C3%A9");
+
+    for key in ["", "entry:a", "a/b\\c", "100%", "%41", "é ü"].iter() {
+        assert_eq!(decode_key(&encode_key(key)), Some(key.to_string()));
+    }
+
+    assert_eq!(decode_key("%4"), None);
+    assert_eq!(decode_key("%G1"), None);
+    assert_eq!(decode_key("%FF"), None);
+}
@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(unix)]
+use std::thread;
+use std::time::Duration;
+
+use futures;
+use futures::{Future, Stream};
+use futures::future::FutureResult;
+
+use hyper;
+use hyper::{Chunk, StatusCode};
+use hyper::header::Location;
+use hyper::server::{Http, NewService, Request, Response, Service};
+
+use rustls::{Certificate, NoClientAuth, PrivateKey, ServerConfig};
+use rustls::internal::pemfile;
+#[cfg(unix)]
+use signal_hook;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Timeout};
+use tokio_rustls::TlsAcceptor;
+
+// accept errors like running out of file descriptors resolve themselves once connections close
+const ACCEPT_ERROR_DELAY_MS: u64 = 100;
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>, String> {
+    let file = File::open(path).map_err(|err| format!("failed to open {}: {}", path, err))?;
+    match pemfile::certs(&mut BufReader::new(file)) {
+        Ok(ref certs) if certs.is_empty() => Err(format!("no certificate found in {}", path)),
+        Ok(certs) => Ok(certs),
+        Err(_) => Err(format!("invalid certificate file {}", path)),
+    }
+}
+
+fn load_key(path: &str) -> Result<PrivateKey, String> {
+    // PKCS#8 keys are tried first, then PKCS#1 RSA keys
+    let file = File::open(path).map_err(|err| format!("failed to open {}: {}", path, err))?;
+    let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(file)).unwrap_or_default();
+
+    if keys.is_empty() {
+        let file = File::open(path).map_err(|err| format!("failed to open {}: {}", path, err))?;
+        keys = pemfile::rsa_private_keys(&mut BufReader::new(file)).unwrap_or_default();
+    }
+
+    keys.into_iter().next().ok_or_else(|| format!("no private key found in {}", path))
+}
+
+fn load_server_config(cert_path: &str, key_path: &str) -> Result<ServerConfig, String> {
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.set_single_cert(certs, key)
+        .map_err(|err| format!("invalid certificate or private key: {:?}", err))?;
+    Ok(config)
+}
+
+/// TLS server configuration that can be reloaded while connections are being accepted.
+#[derive(Clone)]
+pub struct TlsConfig {
+    cert_path: String,
+    key_path: String,
+    current: Arc<RwLock<Arc<ServerConfig>>>,
+}
+
+impl TlsConfig {
+    pub fn load(cert_path: &str, key_path: &str) -> Result<Self, String> {
+        let config = load_server_config(cert_path, key_path)?;
+
+        Ok(TlsConfig {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        })
+    }
+
+    /// Reloads the certificate and private key, keeping the current ones if they are invalid.
+    pub fn reload(&self) -> Result<(), String> {
+        let config = load_server_config(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap() = Arc::new(config);
+        Ok(())
+    }
+
+    /// Reloads the certificate and private key whenever the process receives SIGHUP.
+    #[cfg(unix)]
+    pub fn reload_on_sighup(&self) -> io::Result<()> {
+        let reload = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::SIGHUP, reload.clone())?;
+
+        let tls_config = self.clone();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(Duration::from_secs(1));
+
+                if reload.swap(false, Ordering::SeqCst) {
+                    match tls_config.reload() {
+                        Ok(_) => info!("reloaded tls certificate {}", tls_config.cert_path),
+                        Err(err) => warn!("failed to reload tls certificate: {}", err),
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Other platforms have no SIGHUP, so certificates are only loaded at startup there.
+    #[cfg(not(unix))]
+    pub fn reload_on_sighup(&self) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "SIGHUP is not available on this platform"))
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        // existing connections keep the configuration they were accepted with
+        TlsAcceptor::from(self.current.read().unwrap().clone())
+    }
+}
+
+/// Redirects every plain HTTP request to the same path on the external URL.
+struct RedirectService {
+    external_url: String,
+}
+
+impl Service for RedirectService {
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = FutureResult<Response, hyper::Error>;
+
+    fn call(&self, request: Request) -> Self::Future {
+        let location = match request.query() {
+            Some(query) => format!("{}{}?{}", self.external_url, request.path(), query),
+            None => format!("{}{}", self.external_url, request.path()),
+        };
+
+        futures::future::ok(Response::new()
+            .with_status(StatusCode::PermanentRedirect)
+            .with_header(Location::new(location)))
+    }
+}
+
+/// Serves HTTPS on the given address, with an optional plain HTTP listener redirecting to the external URL.
+pub fn serve<S>(address: SocketAddr, new_service: S, tls_config: TlsConfig,
+                redirect: Option<(SocketAddr, String)>) -> io::Result<()>
+    where S: NewService<Request=Request, Response=Response, Error=hyper::Error> + 'static
+{
+    let mut core = Core::new()?;
+    let handle = core.handle();
+    let mut http = Http::<Chunk>::new();
+    http.sleep_on_errors(true);
+
+    if let Some((redirect_address, external_url)) = redirect {
+        let external_url = external_url.trim_end_matches('/').to_string();
+        let new_redirect_service = move || Ok(RedirectService { external_url: external_url.clone() });
+        let serve = http.serve_addr_handle(&redirect_address, &handle, new_redirect_service)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+
+        let spawn_handle = handle.clone();
+        handle.spawn(serve.for_each(move |connection| {
+            spawn_handle.spawn(connection.map(|_| ())
+                .map_err(|err| debug!("redirect connection error: {}", err)));
+            Ok(())
+        }).map_err(|err| warn!("redirect listener error: {}", err)));
+
+        info!("redirecting http requests at {}", redirect_address);
+    }
+
+    let listener = TcpListener::bind(&address, &handle)?;
+
+    // a failed connection must not stop the server, so errors are logged instead of ending the stream
+    let server = listener.incoming().then(|accepted| Ok::<_, io::Error>(accepted)).for_each(move |accepted| {
+        let socket = match accepted {
+            Ok((socket, _)) => socket,
+            Err(err) => {
+                warn!("failed to accept connection: {}", err);
+                let delay = Timeout::new(Duration::from_millis(ACCEPT_ERROR_DELAY_MS), &handle);
+                return Box::new(futures::future::result(delay).flatten()) as Box<Future<Item=(), Error=io::Error>>;
+            }
+        };
+
+        let service = match new_service.new_service() {
+            Ok(service) => service,
+            Err(err) => {
+                warn!("failed to create service: {}", err);
+                return Box::new(futures::future::ok(()));
+            }
+        };
+        let http = http.clone();
+
+        let connection = tls_config.acceptor().accept(socket)
+            .map_err(|err| debug!("tls handshake error: {}", err))
+            .and_then(move |stream| {
+                http.serve_connection(stream, service)
+                    .map(|_| ())
+                    .map_err(|err| debug!("connection error: {}", err))
+            });
+
+        handle.spawn(connection);
+        Box::new(futures::future::ok(()))
+    });
+
+    core.run(server)
+}
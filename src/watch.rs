@@ -0,0 +1,197 @@
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use futures::{future, Future};
+use futures::executor::{self, Notify};
+use futures::sync::{mpsc, oneshot};
+
+use hyper;
+use hyper::{Body, Chunk};
+
+use serde_json;
+
+use crate::api::EntryInfo;
+use crate::cache::{QuiViveCache, QuiViveEntry};
+use crate::notify::Listener;
+use crate::service::get_timestamp;
+use crate::store::StoreError;
+
+// comment lines keep idle event streams open through proxies
+const HEARTBEAT_INTERVAL_SECS: u64 = 15;
+
+// events not yet received by the client, beyond which the client is considered gone
+const EVENT_BUFFER_SIZE: usize = 16;
+
+type WaitResult = Result<Option<QuiViveEntry>, StoreError>;
+
+// tries to read the entry whenever it changes, until the deadline
+struct Waiter {
+    cache: QuiViveCache,
+    id: String,
+    deadline: Instant,
+    sender: Option<oneshot::Sender<WaitResult>>,
+}
+
+impl Listener for Waiter {
+    fn notify(&mut self, now: Instant) -> Option<Instant> {
+        match self.cache.get_for_read(self.id.clone()) {
+            // another request may have taken the last read, so waiting goes on
+            Ok(None) if now < self.deadline => Some(self.deadline),
+            result => {
+                if let Some(sender) = self.sender.take() {
+                    let _ = sender.send(result);
+                }
+                None
+            }
+        }
+    }
+
+    fn is_closed(&mut self) -> bool {
+        self.sender.as_ref().map_or(true, |sender| sender.is_canceled())
+    }
+}
+
+/// Waits for an entry to be set, then gets it for a read. The entry is None if the timeout elapsed first.
+/// Returns None if too many requests are waiting already.
+pub fn wait_for_entry(cache: QuiViveCache, id: String, timeout: Duration)
+    -> Option<Box<Future<Item=Option<QuiViveEntry>, Error=StoreError>>>
+{
+    let (tx, rx) = oneshot::channel();
+
+    let waiter = Waiter {
+        cache: cache.clone(),
+        id: id.clone(),
+        deadline: Instant::now() + timeout,
+        sender: Some(tx),
+    };
+
+    if cache.listen(&id, Box::new(waiter)).is_err() {
+        return None;
+    }
+
+    Some(Box::new(rx.then(|result| {
+        match result {
+            Ok(result) => result,
+            Err(_) => Err(StoreError::Other("wait canceled".to_string())),
+        }
+    })))
+}
+
+fn same_content(a: &QuiViveEntry, b: &QuiViveEntry) -> bool {
+    // the read count is left out, since reading an entry does not update it
+    a.val == b.val && a.url == b.url && a.content_type == b.content_type &&
+        a.encoding == b.encoding && a.created == b.created && a.expires == b.expires &&
+        a.max_reads == b.max_reads
+}
+
+fn entry_event(name: &str, entry: &QuiViveEntry, now: u32) -> String {
+    // the value is left out, since sending it would not count as a read
+    let data = serde_json::to_string(&EntryInfo::new(entry, now).without_value()).unwrap_or_default();
+    format!("event: {}\ndata: {}\n\n", name, data)
+}
+
+fn removal_event(name: &str, id: &str) -> String {
+    format!("event: {}\ndata: {}\n\n", name, json!({ "id": id }))
+}
+
+struct NoopNotify;
+
+impl Notify for NoopNotify {
+    fn notify(&self, _id: usize) {}
+}
+
+static NOOP_NOTIFY: NoopNotify = NoopNotify;
+
+type EventSender = mpsc::Sender<Result<Chunk, hyper::Error>>;
+
+// the receiving end is dropped along with the response body when the client goes away
+fn is_disconnected(sender: &mut EventSender) -> bool {
+    let mut ready = executor::spawn(future::poll_fn(|| sender.poll_ready()));
+    ready.poll_future_notify(&&NOOP_NOTIFY, 0).is_err()
+}
+
+// sends an event whenever the entry changes, and a heartbeat when the stream is idle
+struct Watcher {
+    cache: QuiViveCache,
+    id: String,
+    previous: Option<QuiViveEntry>,
+    started: bool,
+    sender: EventSender,
+    last_sent: Instant,
+}
+
+impl Listener for Watcher {
+    fn notify(&mut self, now: Instant) -> Option<Instant> {
+        let current = match self.cache.get(self.id.clone()) {
+            Ok(current) => current,
+            Err(err) => {
+                // ending the stream lets the client reconnect, instead of silently missing changes
+                warn!("failed to watch {}: {:?}", self.id, err);
+                return None;
+            }
+        };
+
+        let timestamp = get_timestamp();
+        let heartbeat = Duration::from_secs(HEARTBEAT_INTERVAL_SECS);
+
+        let event = match (&self.previous, &current) {
+            _ if !self.started => None,
+            (&None, &Some(ref entry)) => Some(entry_event("create", entry, timestamp)),
+            (&Some(ref old), &Some(ref entry)) if !same_content(old, entry) => Some(entry_event("update", entry, timestamp)),
+            (&Some(ref old), &None) if old.expiration().map_or(false, |expires| expires <= timestamp) => {
+                Some(removal_event("expire", &self.id))
+            }
+            (&Some(_), &None) => Some(removal_event("delete", &self.id)),
+            _ => None,
+        };
+
+        let event = match event {
+            Some(event) => Some(event),
+            None if now >= self.last_sent + heartbeat => Some(": heartbeat\n\n".to_string()),
+            None => None,
+        };
+
+        if let Some(event) = event {
+            // a full buffer means that the client stopped reading
+            if self.sender.try_send(Ok(Chunk::from(event))).is_err() {
+                return None;
+            }
+            self.last_sent = now;
+        }
+
+        self.started = true;
+        self.previous = current;
+
+        // expiring entries are not notified as changes, so the entry is checked again once it expired
+        let deadline = self.last_sent + heartbeat;
+        match self.previous.as_ref().and_then(|entry| entry.ttl(timestamp)) {
+            Some(ttl) => Some(cmp::min(deadline, now + Duration::from_secs(ttl as u64 + 1))),
+            None => Some(deadline),
+        }
+    }
+
+    fn is_closed(&mut self) -> bool {
+        is_disconnected(&mut self.sender)
+    }
+}
+
+/// Streams server-sent events when an entry is created, updated, deleted or expires.
+/// Returns None if too many requests are waiting already.
+pub fn watch_entry(cache: QuiViveCache, id: String) -> Option<Body> {
+    let (sender, receiver) = mpsc::channel(EVENT_BUFFER_SIZE);
+
+    let watcher = Watcher {
+        cache: cache.clone(),
+        id: id.clone(),
+        previous: None,
+        started: false,
+        sender: sender,
+        last_sent: Instant::now(),
+    };
+
+    if cache.listen(&id, Box::new(watcher)).is_err() {
+        return None;
+    }
+
+    Some(Body::from(receiver))
+}